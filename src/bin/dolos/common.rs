@@ -1,6 +1,6 @@
 use dolos::{ledger::pparams::Genesis, state, wal};
 use miette::{Context as _, IntoDiagnostic};
-use std::{path::PathBuf, time::Duration};
+use std::{path::PathBuf, sync::OnceLock, time::Duration};
 use tokio::task::JoinHandle;
 use tokio_util::sync::CancellationToken;
 use tracing::{debug, warn};
@@ -12,24 +12,46 @@ use crate::{GenesisConfig, LoggingConfig};
 
 pub type Stores = (wal::redb::WalStore, state::LedgerStore);
 
+static STORAGE_LOCK: OnceLock<dolos::lock::StorageLock> = OnceLock::new();
+
+/// Makes sure that no other process is using the storage path
+///
+/// The lock is kept until the process exits, so it's fine to call this more
+/// than once.
+pub fn lock_storage(config: &crate::Config) -> Result<(), Error> {
+    if STORAGE_LOCK.get().is_some() {
+        return Ok(());
+    }
+
+    let lock = dolos::lock::StorageLock::acquire(&config.storage.path).map_err(Error::storage)?;
+    let _ = STORAGE_LOCK.set(lock);
+
+    Ok(())
+}
+
 pub fn open_wal(config: &crate::Config) -> Result<wal::redb::WalStore, Error> {
     let root = &config.storage.path;
 
     std::fs::create_dir_all(root).map_err(Error::storage)?;
+    lock_storage(config)?;
 
-    let wal = wal::redb::WalStore::open(
+    let mut wal = wal::redb::WalStore::open(
         root.join("wal"),
         config.storage.wal_cache,
         config.storage.max_wal_history,
     )
     .map_err(Error::storage)?;
 
+    wal.set_durability(config.storage.durability);
+    wal.set_max_rollbacks(config.storage.max_rollbacks);
+
     Ok(wal)
 }
 
 pub fn define_ledger_path(config: &crate::Config) -> Result<PathBuf, Error> {
     let root = &config.storage.path;
     std::fs::create_dir_all(root).map_err(Error::storage)?;
+    lock_storage(config)?;
 
     let ledger = root.join("ledger");
 
@@ -40,17 +62,24 @@ pub fn open_data_stores(config: &crate::Config) -> Result<Stores, Error> {
     let root = &config.storage.path;
 
     std::fs::create_dir_all(root).map_err(Error::storage)?;
+    lock_storage(config)?;
 
-    let wal = wal::redb::WalStore::open(
+    let mut wal = wal::redb::WalStore::open(
         root.join("wal"),
         config.storage.wal_cache,
         config.storage.max_wal_history,
     )
     .map_err(Error::storage)?;
 
-    let ledger = state::redb::LedgerStore::open(root.join("ledger"), config.storage.ledger_cache)
-        .map_err(Error::storage)?
-        .into();
+    wal.set_durability(config.storage.durability);
+    wal.set_max_rollbacks(config.storage.max_rollbacks);
+
+    let mut ledger: state::LedgerStore =
+        state::redb::LedgerStore::open(root.join("ledger"), config.storage.ledger_cache)
+            .map_err(Error::storage)?
+            .into();
+
+    ledger.set_durability(config.storage.durability);
 
     Ok((wal, ledger))
 }
@@ -119,6 +148,7 @@ pub fn open_genesis_files(config: &GenesisConfig) -> miette::Result<Genesis> {
         alonzo: alonzo_genesis,
         conway: conway_genesis,
         force_protocol: config.force_protocol,
+        cost_model_tolerance: config.cost_model_tolerance,
     })
 }
 
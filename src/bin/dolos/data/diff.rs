@@ -0,0 +1,143 @@
+use comfy_table::Table;
+use dolos::state::{
+    self,
+    diff::{DiffOptions, Namespace, NamespaceDiff},
+};
+use miette::{bail, Context, IntoDiagnostic};
+use std::path::PathBuf;
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// storage path of the dolos instance to compare against
+    #[arg(long)]
+    other: PathBuf,
+
+    /// only compare one namespace (cursor, pparams or utxos)
+    #[arg(long)]
+    namespace: Option<Namespace>,
+
+    /// compare this many random utxos from each side instead of the whole set
+    #[arg(long)]
+    sample: Option<usize>,
+
+    /// seed for the random choice of sampled utxos, random if not set
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// max number of differences to show per namespace
+    #[arg(long, default_value_t = 10)]
+    max_details: usize,
+
+    /// output the report as JSON
+    #[arg(long, action)]
+    json: bool,
+}
+
+fn print_diff(diff: &NamespaceDiff) {
+    println!("{}", diff.namespace);
+
+    let count = |x: Option<u64>| x.map(|x| x.to_string()).unwrap_or("-".into());
+
+    let mut table = Table::new();
+    table.set_header(vec![
+        "matching",
+        "mismatched",
+        "only local",
+        "only other",
+        "local count",
+        "other count",
+    ]);
+
+    table.add_row(vec![
+        diff.matching.to_string(),
+        diff.mismatched.to_string(),
+        diff.only_local.to_string(),
+        diff.only_other.to_string(),
+        count(diff.local_count),
+        count(diff.other_count),
+    ]);
+
+    println!("{table}");
+
+    if diff.sampled {
+        println!("only a sample of the entries was compared");
+    }
+
+    for x in diff.details.iter() {
+        let local = x.local.as_ref().map(|x| x.to_string());
+        let other = x.other.as_ref().map(|x| x.to_string());
+
+        println!("{}", x.key);
+        println!("  local: {}", local.as_deref().unwrap_or("missing"));
+        println!("  other: {}", other.as_deref().unwrap_or("missing"));
+    }
+}
+
+pub fn run(config: &crate::Config, args: &Args) -> miette::Result<()> {
+    crate::common::setup_tracing(&config.logging)?;
+
+    let other_path = args.other.join("ledger");
+
+    if !other_path.exists() {
+        bail!("no ledger found at {}", other_path.display());
+    }
+
+    let local = crate::common::define_ledger_path(config).context("defining ledger path")?;
+
+    let local = state::redb::LedgerStore::open(local, config.storage.ledger_cache)
+        .into_diagnostic()
+        .context("opening local ledger")?;
+
+    // make sure the other instance isn't writing while we read
+    let _lock = dolos::lock::StorageLock::acquire(&args.other)
+        .into_diagnostic()
+        .context("locking other storage")?;
+
+    let other = state::redb::LedgerStore::open(other_path, None)
+        .into_diagnostic()
+        .context("opening other ledger")?;
+
+    let seed = args.seed.unwrap_or_else(|| {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos() as u64
+    });
+
+    let options = DiffOptions {
+        max_details: args.max_details,
+        sample: args.sample,
+        seed,
+    };
+
+    let namespaces = match args.namespace {
+        Some(x) => vec![x],
+        None => Namespace::ALL.to_vec(),
+    };
+
+    let mut diffs = vec![];
+
+    for namespace in namespaces {
+        let diff = state::diff::diff(&local, &other, namespace, &options)
+            .into_diagnostic()
+            .with_context(|| format!("comparing {namespace}"))?;
+
+        diffs.push(diff);
+    }
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&diffs).into_diagnostic()?;
+        println!("{json}");
+    } else {
+        for diff in diffs.iter() {
+            print_diff(diff);
+            println!();
+        }
+    }
+
+    if diffs.iter().any(|x| !x.is_match()) {
+        bail!("ledgers differ");
+    }
+
+    Ok(())
+}
@@ -1,7 +1,8 @@
-use dolos::wal::{ChainPoint, WalReader as _};
+use dolos::{
+    ledger,
+    wal::{ChainPoint, WalReader as _},
+};
 use miette::{Context, IntoDiagnostic};
-use pallas::crypto::hash::Hash;
-use std::str::FromStr;
 
 #[derive(Debug, clap::Args)]
 pub struct Args {
@@ -19,7 +20,7 @@ pub fn run(config: &crate::Config, args: &Args) -> miette::Result<()> {
 
     let wal = crate::common::open_wal(config).context("opening WAL")?;
 
-    let hash = Hash::from_str(&args.hash)
+    let hash = ledger::parse_hash(&args.hash)
         .into_diagnostic()
         .context("error parsing hash")?;
 
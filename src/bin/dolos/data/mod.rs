@@ -1,10 +1,13 @@
 use clap::{Parser, Subcommand};
 
 mod copy_wal;
+mod diff;
 mod dump_wal;
 mod export;
 mod find_seq;
 mod prune_wal;
+mod rollbacks;
+mod stats;
 mod summary;
 
 #[derive(Debug, Subcommand)]
@@ -21,6 +24,12 @@ pub enum Command {
     CopyWal(copy_wal::Args),
     /// removes blocks from the WAL before a given slot
     PruneWal(prune_wal::Args),
+    /// shows the size and key count of each data store
+    Stats(stats::Args),
+    /// compares the ledger against the one of another data directory
+    Diff(diff::Args),
+    /// shows the recent rollbacks applied to the WAL
+    Rollbacks(rollbacks::Args),
 }
 
 #[derive(Debug, Parser)]
@@ -41,6 +50,9 @@ pub fn run(
         Command::Export(x) => export::run(config, x, feedback)?,
         Command::CopyWal(x) => copy_wal::run(config, x)?,
         Command::PruneWal(x) => prune_wal::run(config, x)?,
+        Command::Stats(x) => stats::run(config, x)?,
+        Command::Diff(x) => diff::run(config, x)?,
+        Command::Rollbacks(x) => rollbacks::run(config, x)?,
     }
 
     Ok(())
@@ -0,0 +1,114 @@
+use comfy_table::Table;
+use dolos::wal::RollbackRecord;
+use miette::{Context, IntoDiagnostic};
+use serde::Serialize;
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// max number of rollbacks to list, most recent first
+    #[arg(long, default_value_t = 20)]
+    limit: usize,
+
+    /// output the rollbacks as JSON
+    #[arg(long, action)]
+    json: bool,
+}
+
+/// Upper bound (inclusive) of each bucket of the depth histogram, in blocks
+const DEPTH_BUCKETS: [u64; 5] = [1, 2, 5, 10, 50];
+
+#[derive(Serialize)]
+struct Bucket {
+    label: String,
+    count: usize,
+}
+
+fn histogram(records: &[RollbackRecord]) -> Vec<Bucket> {
+    let mut lower = 1;
+    let mut buckets = vec![];
+
+    for upper in DEPTH_BUCKETS {
+        let count = records
+            .iter()
+            .filter(|x| x.depth_blocks >= lower && x.depth_blocks <= upper)
+            .count();
+
+        let label = match lower == upper {
+            true => upper.to_string(),
+            false => format!("{lower}-{upper}"),
+        };
+
+        buckets.push(Bucket { label, count });
+        lower = upper + 1;
+    }
+
+    let count = records.iter().filter(|x| x.depth_blocks >= lower).count();
+
+    buckets.push(Bucket {
+        label: format!("{lower}+"),
+        count,
+    });
+
+    buckets
+}
+
+#[derive(Serialize)]
+struct Report<'a> {
+    total: usize,
+    histogram: Vec<Bucket>,
+    recent: Vec<&'a RollbackRecord>,
+}
+
+fn print_report(report: &Report) {
+    println!("rollbacks logged: {}", report.total);
+
+    let mut table = Table::new();
+    table.set_header(vec!["depth (blocks)", "count"]);
+
+    for bucket in report.histogram.iter() {
+        table.add_row(vec![bucket.label.clone(), bucket.count.to_string()]);
+    }
+
+    println!("{table}");
+
+    let mut table = Table::new();
+    table.set_header(vec!["timestamp", "from", "to", "blocks", "slots"]);
+
+    for x in report.recent.iter() {
+        table.add_row(vec![
+            x.timestamp.to_string(),
+            x.from.to_string(),
+            x.to.to_string(),
+            x.depth_blocks.to_string(),
+            x.depth_slots.to_string(),
+        ]);
+    }
+
+    println!("{table}");
+}
+
+pub fn run(config: &crate::Config, args: &Args) -> miette::Result<()> {
+    crate::common::setup_tracing(&config.logging)?;
+
+    let wal = crate::common::open_wal(config).context("opening WAL")?;
+
+    let records = wal
+        .rollback_history()
+        .into_diagnostic()
+        .context("reading rollback log")?;
+
+    let report = Report {
+        total: records.len(),
+        histogram: histogram(&records),
+        recent: records.iter().rev().take(args.limit).collect(),
+    };
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&report).into_diagnostic()?;
+        println!("{json}");
+    } else {
+        print_report(&report);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,125 @@
+use comfy_table::Table;
+use dolos::{
+    model::TableStats,
+    wal::{ChainPoint, WalReader as _},
+};
+use miette::{Context, IntoDiagnostic};
+use serde::Serialize;
+use std::path::Path;
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// output the stats as JSON
+    #[arg(long, action)]
+    json: bool,
+}
+
+#[derive(Serialize)]
+struct StoreStats {
+    name: &'static str,
+    disk_bytes: Option<u64>,
+    tables: Vec<TableStats>,
+    start_slot: Option<u64>,
+    tip_slot: Option<u64>,
+    max_history: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Stats {
+    stores: Vec<StoreStats>,
+    total_disk_bytes: u64,
+}
+
+fn disk_size(path: &Path) -> Option<u64> {
+    std::fs::metadata(path).ok().map(|x| x.len())
+}
+
+fn point_slot(point: Option<(u64, ChainPoint)>) -> Option<u64> {
+    match point {
+        Some((_, ChainPoint::Specific(slot, _))) => Some(slot),
+        Some((_, ChainPoint::Origin)) => Some(0),
+        None => None,
+    }
+}
+
+fn print_store(store: &StoreStats) {
+    println!("{}", store.name);
+
+    if let Some(bytes) = store.disk_bytes {
+        println!("disk size: {bytes} bytes");
+    }
+
+    if let (Some(start), Some(tip)) = (store.start_slot, store.tip_slot) {
+        println!("slot range: {start} - {tip}");
+    }
+
+    match store.max_history {
+        Some(x) => println!("max history: {x} slots"),
+        None => println!("max history: unlimited"),
+    }
+
+    let mut table = Table::new();
+    table.set_header(vec!["table", "keys", "stored", "metadata", "fragmented"]);
+
+    for x in store.tables.iter() {
+        table.add_row(vec![
+            x.name.clone(),
+            x.keys.to_string(),
+            x.stored_bytes.to_string(),
+            x.metadata_bytes.to_string(),
+            x.fragmented_bytes.to_string(),
+        ]);
+    }
+
+    println!("{table}");
+}
+
+pub fn run(config: &crate::Config, args: &Args) -> miette::Result<()> {
+    crate::common::setup_tracing(&config.logging)?;
+
+    let (wal, ledger) = crate::common::open_data_stores(config).context("opening data stores")?;
+
+    let root = &config.storage.path;
+
+    let wal_stats = StoreStats {
+        name: "wal",
+        disk_bytes: disk_size(&root.join("wal")),
+        tables: wal.stats().into_diagnostic().context("reading wal stats")?,
+        start_slot: point_slot(wal.find_start().into_diagnostic()?),
+        tip_slot: point_slot(wal.find_tip().into_diagnostic()?),
+        max_history: config.storage.max_wal_history,
+    };
+
+    let ledger_stats = StoreStats {
+        name: "ledger",
+        disk_bytes: disk_size(&root.join("ledger")),
+        tables: ledger
+            .stats()
+            .into_diagnostic()
+            .context("reading ledger stats")?,
+        start_slot: None,
+        tip_slot: ledger.cursor().into_diagnostic()?.map(|x| x.0),
+        max_history: None,
+    };
+
+    let stores = vec![wal_stats, ledger_stats];
+
+    let stats = Stats {
+        total_disk_bytes: stores.iter().filter_map(|x| x.disk_bytes).sum(),
+        stores,
+    };
+
+    if args.json {
+        let json = serde_json::to_string_pretty(&stats).into_diagnostic()?;
+        println!("{json}");
+    } else {
+        for store in stats.stores.iter() {
+            print_store(store);
+            println!();
+        }
+
+        println!("total disk size: {} bytes", stats.total_disk_bytes);
+    }
+
+    Ok(())
+}
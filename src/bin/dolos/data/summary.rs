@@ -113,7 +113,7 @@ pub fn run(config: &crate::Config, _args: &Args) -> miette::Result<()> {
 
     let genesis = crate::common::open_genesis_files(&config.genesis)?;
 
-    let eras = dolos::ledger::pparams::fold(&genesis, &updates);
+    let eras = dolos::ledger::pparams::fold(&genesis, &updates).into_diagnostic()?;
 
     println!("{:?}", eras);
 
@@ -3,14 +3,20 @@ use clap::{Parser, Subcommand};
 use crate::feedback::Feedback;
 
 mod rebuild_ledger;
+mod rewind;
+mod simulate_boundary;
 mod wal_integrity;
 
 #[derive(Debug, Subcommand)]
 pub enum Command {
     /// rebuilds the whole ledger from chain data
     RebuildLedger(rebuild_ledger::Args),
-    /// checks the integrity of the WAL records
+    /// checks the integrity of the WAL records by re-validating stored blocks
     WalIntegrity(wal_integrity::Args),
+    /// resets the chain data to a specific point
+    Rewind(rewind::Args),
+    /// simulates the pparams of an epoch boundary without touching the stores
+    SimulateBoundary(simulate_boundary::Args),
 }
 
 #[derive(Debug, Parser)]
@@ -22,12 +28,19 @@ pub struct Args {
 pub fn run(config: &super::Config, args: &Args, feedback: &Feedback) -> miette::Result<()> {
     match &args.command {
         Command::RebuildLedger(x) => rebuild_ledger::run(config, x, feedback)?,
-        Command::WalIntegrity(x) => wal_integrity::run(config, x)?,
+        Command::WalIntegrity(x) => wal_integrity::run(config, x, feedback)?,
+        Command::Rewind(x) => rewind::run(config, x, feedback)?,
+        Command::SimulateBoundary(x) => simulate_boundary::run(config, x)?,
     }
 
     Ok(())
 }
 
 pub fn run_rebuild_ledger(config: &super::Config, feedback: &Feedback) -> miette::Result<()> {
-    rebuild_ledger::run(config, &rebuild_ledger::Args, feedback)
+    let args = rebuild_ledger::Args {
+        yes: true,
+        ..Default::default()
+    };
+
+    rebuild_ledger::run(config, &args, feedback)
 }
@@ -1,20 +1,130 @@
 use dolos::{
-    ledger,
-    wal::{self, RawBlock, ReadUtils, WalReader as _},
+    ledger::pparams::Genesis,
+    wal::{self, LogValue, RawBlock, WalReader as _},
 };
 use itertools::Itertools;
-use miette::{Context, IntoDiagnostic};
+use miette::{bail, Context, IntoDiagnostic};
 use pallas::ledger::traverse::MultiEraBlock;
 use tracing::debug;
 
-use crate::feedback::Feedback;
+use crate::feedback::{Feedback, ProgressBar};
 
-#[derive(Debug, clap::Args)]
-pub struct Args;
+#[derive(Debug, Default, clap::Args)]
+pub struct Args {
+    /// wipe the existing ledger without asking for confirmation
+    #[arg(long, action)]
+    pub yes: bool,
 
-pub fn run(config: &crate::Config, _args: &Args, feedback: &Feedback) -> miette::Result<()> {
+    /// stop replaying blocks after this slot
+    #[arg(long)]
+    pub stop_slot: Option<u64>,
+}
+
+/// Blocks applied to the ledger in a single batch while replaying the WAL
+const BATCH_SIZE: usize = 100;
+
+fn apply_batch(
+    batch: &mut Vec<RawBlock>,
+    ledger: &dolos::state::LedgerStore,
+    genesis: &Genesis,
+) -> miette::Result<()> {
+    if batch.is_empty() {
+        return Ok(());
+    }
+
+    let blocks: Vec<_> = batch
+        .iter()
+        .map(|RawBlock { body, .. }| MultiEraBlock::decode(body))
+        .try_collect()
+        .into_diagnostic()
+        .context("decoding blocks")?;
+
+    dolos::state::apply_block_batch(&blocks, ledger, genesis)
+        .into_diagnostic()
+        .context("importing blocks to ledger store")?;
+
+    batch.clear();
+
+    Ok(())
+}
+
+fn undo_block(block: &RawBlock, ledger: &dolos::state::LedgerStore) -> miette::Result<()> {
+    let block = MultiEraBlock::decode(&block.body)
+        .into_diagnostic()
+        .context("decoding block")?;
+
+    dolos::state::undo_block(&block, ledger)
+        .into_diagnostic()
+        .context("undoing block in ledger store")
+}
+
+/// Replays the entries of the WAL into the ledger the same way the apply stage
+/// does, so blocks of rolled back forks are undone
+fn replay(
+    entries: impl Iterator<Item = LogValue>,
+    ledger: &dolos::state::LedgerStore,
+    genesis: &Genesis,
+    progress: &ProgressBar,
+) -> miette::Result<()> {
+    let mut batch = Vec::with_capacity(BATCH_SIZE);
+    let mut block_count = 0;
+
+    for log in entries {
+        match log {
+            LogValue::Apply(block) => {
+                progress.set_position(block.slot);
+                batch.push(block);
+                block_count += 1;
+
+                if batch.len() >= BATCH_SIZE {
+                    apply_batch(&mut batch, ledger, genesis)?;
+                    progress.set_message(format!("rebuilding ledger ({block_count} blocks)"));
+                }
+            }
+            LogValue::Undo(block) => {
+                // the undone block might still be waiting in the batch
+                apply_batch(&mut batch, ledger, genesis)?;
+                undo_block(&block, ledger)?;
+            }
+            LogValue::Mark(wal::ChainPoint::Origin) => {
+                apply_batch(&mut batch, ledger, genesis)?;
+
+                dolos::state::apply_origin(ledger, genesis)
+                    .into_diagnostic()
+                    .context("applying origin utxos")?;
+            }
+            LogValue::Mark(..) => (),
+        }
+    }
+
+    apply_batch(&mut batch, ledger, genesis)?;
+    progress.set_message(format!("rebuilding ledger ({block_count} blocks)"));
+
+    Ok(())
+}
+
+pub fn run(config: &crate::Config, args: &Args, feedback: &Feedback) -> miette::Result<()> {
     //crate::common::setup_tracing(&config.logging)?;
 
+    let ledger_path = crate::common::define_ledger_path(config).context("finding ledger path")?;
+
+    if ledger_path.exists() && !args.yes {
+        bail!(
+            "ledger already exists at {}, use --yes to wipe it and rebuild",
+            ledger_path.display()
+        );
+    }
+
+    // the ledger is built aside and only replaces the existing one once the
+    // rebuild is complete, so a failed rebuild leaves the node as it was
+    let rebuild_path = ledger_path.with_extension("rebuild");
+
+    if rebuild_path.exists() {
+        std::fs::remove_file(&rebuild_path)
+            .into_diagnostic()
+            .context("removing leftovers of a previous rebuild")?;
+    }
+
     let progress = feedback.slot_progress_bar();
     progress.set_message("rebuilding ledger");
 
@@ -49,48 +159,27 @@ pub fn run(config: &crate::Config, _args: &Args, feedback: &Feedback) -> miette:
         .context("finding WAL tip")?
         .ok_or(miette::miette!("no WAL tip found"))?;
 
-    match tip {
-        wal::ChainPoint::Origin => progress.set_length(0),
-        wal::ChainPoint::Specific(slot, _) => progress.set_length(slot),
+    match (tip, args.stop_slot) {
+        (_, Some(slot)) => progress.set_length(slot),
+        (wal::ChainPoint::Origin, _) => progress.set_length(0),
+        (wal::ChainPoint::Specific(slot, _), _) => progress.set_length(slot),
     }
 
-    let wal_seq = light
-        .cursor()
-        .into_diagnostic()
-        .context("finding ledger cursor")?
-        .map(|ledger::ChainPoint(s, h)| wal.assert_point(&wal::ChainPoint::Specific(s, h)))
-        .transpose()
-        .into_diagnostic()
-        .context("locating wal sequence")?;
-
-    let remaining = wal
-        .crawl_from(wal_seq)
+    // the origin utxos were imported above, only later marks need to apply them
+    let entries = wal
+        .crawl_from(None)
         .into_diagnostic()
         .context("crawling wal")?
-        .filter_forward()
-        .into_blocks()
-        .flatten();
+        .map(|(_, log)| log)
+        .skip_while(|log| matches!(log, LogValue::Mark(wal::ChainPoint::Origin)))
+        .take_while(|log| match (log, args.stop_slot) {
+            (LogValue::Apply(block), Some(stop)) => block.slot <= stop,
+            _ => true,
+        });
 
-    for chunk in remaining.chunks(100).into_iter() {
-        let bodies = chunk.map(|RawBlock { body, .. }| body).collect_vec();
+    replay(entries, &light, &genesis, &progress)?;
 
-        let blocks: Vec<_> = bodies
-            .iter()
-            .map(|b| MultiEraBlock::decode(b))
-            .try_collect()
-            .into_diagnostic()
-            .context("decoding blocks")?;
-
-        dolos::state::apply_block_batch(&blocks, &light, &genesis)
-            .into_diagnostic()
-            .context("importing blocks to ledger store")?;
-
-        blocks.last().inspect(|b| progress.set_position(b.slot()));
-    }
-
-    let ledger_path = crate::common::define_ledger_path(config).context("finding ledger path")?;
-
-    let disk = dolos::state::redb::LedgerStore::open_v2_light(ledger_path, None)
+    let disk = dolos::state::redb::LedgerStore::open_v2_light(&rebuild_path, None)
         .into_diagnostic()
         .context("opening ledger db")?;
 
@@ -109,11 +198,18 @@ pub fn run(config: &crate::Config, _args: &Args, feedback: &Feedback) -> miette:
     let pb = feedback.indeterminate_progress_bar();
     pb.set_message("creating indexes");
 
-    disk.upgrade()
+    let disk = disk
+        .upgrade()
         .into_diagnostic()
         .context("creating indexes")?;
 
     pb.abandon_with_message("indexes created");
 
+    drop(disk);
+
+    std::fs::rename(&rebuild_path, &ledger_path)
+        .into_diagnostic()
+        .context("replacing existing ledger")?;
+
     Ok(())
 }
@@ -0,0 +1,361 @@
+use dolos::{
+    ledger,
+    wal::{self, LogValue, RawBlock, WalReader as _},
+};
+use miette::{bail, Context, IntoDiagnostic};
+use pallas::ledger::traverse::MultiEraBlock;
+use tracing::info;
+
+use crate::feedback::{Feedback, ProgressBar};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// slot of the block to rewind to
+    #[arg(long, required_unless_present = "to_hash")]
+    to_slot: Option<u64>,
+
+    /// hash of the block to rewind to as a hex string
+    #[arg(long)]
+    to_hash: Option<String>,
+
+    /// only print what would be done, without modifying any data
+    #[arg(long, action)]
+    dry_run: bool,
+}
+
+/// Finds the latest WAL entry for the target point, returning its sequence
+fn find_target(
+    wal: &wal::redb::WalStore,
+    args: &Args,
+) -> miette::Result<(wal::LogSeq, wal::ChainPoint)> {
+    let hash = args
+        .to_hash
+        .as_deref()
+        .map(ledger::parse_hash)
+        .transpose()
+        .into_diagnostic()
+        .context("parsing target hash")?;
+
+    let found = wal
+        .crawl_from(None)
+        .into_diagnostic()
+        .context("crawling wal")?
+        .rev()
+        .filter_map(|(seq, log)| match log {
+            LogValue::Apply(x) => Some((seq, wal::ChainPoint::from(&x))),
+            LogValue::Mark(x @ wal::ChainPoint::Specific(..)) => Some((seq, x)),
+            _ => None,
+        })
+        .find(|(_, point)| match (point, args.to_slot, hash) {
+            (wal::ChainPoint::Specific(s, h), Some(slot), Some(hash)) => *s == slot && *h == hash,
+            (wal::ChainPoint::Specific(s, _), Some(slot), None) => *s == slot,
+            (wal::ChainPoint::Specific(_, h), None, Some(hash)) => *h == hash,
+            _ => false,
+        });
+
+    match found {
+        Some(x) => Ok(x),
+        None => bail!("target point not found in WAL"),
+    }
+}
+
+/// Gathers the blocks that are applied after the target sequence, ignoring
+/// the ones that were already undone by a rollback. Blocks are returned in the
+/// order they need to be undone (newest first).
+///
+/// Fails if the target itself was undone by a later rollback, since it's not
+/// part of the chain anymore.
+fn find_blocks_to_undo(
+    wal: &wal::redb::WalStore,
+    seq: wal::LogSeq,
+    target: &wal::ChainPoint,
+) -> miette::Result<Vec<RawBlock>> {
+    let mut applied: Vec<RawBlock> = vec![];
+
+    let entries = wal
+        .crawl_from(Some(seq))
+        .into_diagnostic()
+        .context("crawling wal")?
+        .skip(1);
+
+    for (_, log) in entries {
+        match log {
+            LogValue::Apply(x) => applied.push(x),
+            LogValue::Undo(x) => match applied.iter().rposition(|b| b.hash == x.hash) {
+                Some(idx) => applied.truncate(idx),
+                // undos are newest first, an undo of a block that isn't after the
+                // target means that the target was rolled back too
+                None => bail!("can't rewind to {target:?}, it was undone by a later rollback"),
+            },
+            LogValue::Mark(..) => (),
+        }
+    }
+
+    applied.reverse();
+
+    Ok(applied)
+}
+
+/// Reverts the given blocks from the ledger, skipping the ones the ledger
+/// hasn't applied yet
+fn undo_blocks(
+    ledger: &dolos::state::LedgerStore,
+    blocks: &[RawBlock],
+    cursor_slot: u64,
+    progress: &ProgressBar,
+) -> miette::Result<()> {
+    for block in blocks.iter() {
+        if block.slot > cursor_slot {
+            progress.inc(1);
+            continue;
+        }
+
+        let blockd = MultiEraBlock::decode(&block.body)
+            .into_diagnostic()
+            .context("decoding block")?;
+
+        dolos::state::undo_block(&blockd, ledger)
+            .into_diagnostic()
+            .context("undoing block")?;
+
+        info!(slot = block.slot, "block undone");
+        progress.inc(1);
+    }
+
+    Ok(())
+}
+
+pub fn run(config: &crate::Config, args: &Args, feedback: &Feedback) -> miette::Result<()> {
+    crate::common::setup_tracing(&config.logging)?;
+
+    let (mut wal, ledger) =
+        crate::common::open_data_stores(config).context("opening data stores")?;
+
+    let genesis = crate::common::open_genesis_files(&config.genesis)?;
+
+    let (target_seq, target) = find_target(&wal, args)?;
+
+    let (_, start) = wal
+        .find_start()
+        .into_diagnostic()
+        .context("finding WAL start")?
+        .ok_or(miette::miette!("no WAL start found"))?;
+
+    let to_undo = find_blocks_to_undo(&wal, target_seq, &target)?;
+
+    let cursor = ledger
+        .cursor()
+        .into_diagnostic()
+        .context("finding ledger cursor")?;
+
+    let (wal::ChainPoint::Specific(target_slot, _), Some(ledger::ChainPoint(cursor_slot, _))) =
+        (&target, &cursor)
+    else {
+        bail!("can't rewind ledger to origin, use `doctor rebuild-ledger` instead");
+    };
+
+    // undo deltas are only available while the consumed utxos haven't been
+    // finalized, beyond that we need to rebuild the ledger from scratch.
+    let immutable = ledger::lastest_immutable_slot(*cursor_slot, &genesis);
+    let needs_rebuild = *target_slot < immutable;
+
+    println!("rewinding to {target:?} (wal seq {target_seq})");
+    println!("blocks to undo: {}", to_undo.len());
+
+    if needs_rebuild && start != wal::ChainPoint::Origin {
+        bail!(
+            "ledger can't be rebuilt because the WAL doesn't start at origin (starts at {start:?})"
+        );
+    }
+
+    match needs_rebuild {
+        true => println!("target is beyond the immutable slot {immutable}, ledger will be rebuilt"),
+        false => println!("ledger will be rolled back using undo deltas"),
+    }
+
+    if args.dry_run {
+        println!("dry run, no changes were made");
+        return Ok(());
+    }
+
+    if !needs_rebuild {
+        let progress = feedback.slot_progress_bar();
+        progress.set_message("undoing blocks");
+        progress.set_length(to_undo.len() as u64);
+
+        undo_blocks(&ledger, &to_undo, *cursor_slot, &progress)?;
+
+        progress.abandon_with_message("ledger rolled back");
+    }
+
+    wal.truncate_after(&target)
+        .into_diagnostic()
+        .context("truncating WAL")?;
+
+    println!("WAL truncated after {target:?}");
+
+    if needs_rebuild {
+        // release the dbs so that the ledger can be wiped and re-created by the rebuild
+        drop(ledger);
+        drop(wal);
+
+        super::run_rebuild_ledger(config, feedback)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use dolos::ledger::{EraCbor, LedgerDelta, TxoRef, UtxoMap};
+    use dolos::state::LedgerStore;
+    use dolos::wal::WalWriter as _;
+    use itertools::Itertools as _;
+    use pallas::ledger::traverse::Era;
+    use std::collections::HashSet;
+
+    use super::*;
+
+    /// Mainnet blocks with txs, sorted by slot
+    fn load_blocks() -> Vec<RawBlock> {
+        let path = std::path::PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("src/ledger/pparams/test_data/mainnet/update_proposal_blocks");
+
+        std::fs::read_dir(path)
+            .unwrap()
+            .map(|x| std::fs::read(x.unwrap().path()).unwrap())
+            .filter_map(|body| {
+                let block = MultiEraBlock::decode(&body).unwrap();
+
+                if block.txs().is_empty() {
+                    return None;
+                }
+
+                Some(RawBlock {
+                    slot: block.slot(),
+                    hash: block.hash(),
+                    era: block.era(),
+                    body: body.clone(),
+                })
+            })
+            .sorted_by_key(|x| x.slot)
+            .take(10)
+            .collect()
+    }
+
+    /// Creates a ledger with the utxos consumed by the blocks from outside of
+    /// the set, since the blocks aren't consecutive
+    fn seeded_ledger(blocks: &[RawBlock]) -> LedgerStore {
+        let blocks: Vec<_> = blocks
+            .iter()
+            .map(|x| MultiEraBlock::decode(&x.body).unwrap())
+            .collect();
+
+        let produced: HashSet<_> = blocks
+            .iter()
+            .flat_map(|b| b.txs())
+            .flat_map(|tx| {
+                let hash = tx.hash();
+                tx.produces()
+                    .into_iter()
+                    .map(move |(idx, _)| TxoRef(hash, idx as u32))
+            })
+            .collect();
+
+        let seeds: UtxoMap = blocks
+            .iter()
+            .flat_map(|b| b.txs())
+            .flat_map(|tx| tx.consumes())
+            .map(|x| TxoRef(*x.hash(), x.index() as u32))
+            .filter(|x| !produced.contains(x))
+            .map(|x| (x, EraCbor(Era::Byron, vec![])))
+            .collect();
+
+        let ledger =
+            LedgerStore::Redb(dolos::state::redb::LedgerStore::in_memory_v2_light().unwrap());
+
+        ledger
+            .apply(&[LedgerDelta {
+                new_position: Some(ledger::ChainPoint(
+                    0,
+                    pallas::crypto::hash::Hash::new([0; 32]),
+                )),
+                produced_utxo: seeds,
+                ..Default::default()
+            }])
+            .unwrap();
+
+        ledger
+    }
+
+    /// Follows the WAL the way the apply stage does, without finalizing
+    fn follow_wal(wal: &wal::redb::WalStore, ledger: &LedgerStore) {
+        for (_, log) in wal.crawl_from(None).unwrap() {
+            match log {
+                LogValue::Apply(x) => {
+                    let block = MultiEraBlock::decode(&x.body).unwrap();
+                    let context = dolos::state::load_slice_for_block(&block, ledger, &[]).unwrap();
+                    let delta = ledger::compute_delta(&block, context).unwrap();
+                    ledger.apply(&[delta]).unwrap();
+                }
+                LogValue::Undo(x) => {
+                    let block = MultiEraBlock::decode(&x.body).unwrap();
+                    dolos::state::undo_block(&block, ledger).unwrap();
+                }
+                LogValue::Mark(..) => (),
+            }
+        }
+    }
+
+    fn args_for(block: &RawBlock) -> Args {
+        Args {
+            to_slot: Some(block.slot),
+            to_hash: None,
+            dry_run: false,
+        }
+    }
+
+    #[test]
+    fn test_rewind_after_rollback() {
+        let blocks = load_blocks();
+        assert_eq!(blocks.len(), 10);
+
+        // the chain is rolled back to block 5 and then moves forward again up to
+        // block 8, leaving block 9 in an undone fork
+        let mut wal = dolos::wal::testing::empty_db();
+        wal.roll_forward(blocks.iter().cloned()).unwrap();
+        wal.roll_back(&wal::ChainPoint::from(&blocks[5])).unwrap();
+        wal.roll_forward(blocks[6..9].iter().cloned()).unwrap();
+
+        let ledger = seeded_ledger(&blocks);
+        follow_wal(&wal, &ledger);
+
+        let cursor = ledger.cursor().unwrap().unwrap();
+        assert_eq!(cursor, ledger::ChainPoint(blocks[8].slot, blocks[8].hash));
+
+        // blocks of the undone fork aren't valid targets
+        let (seq, target) = find_target(&wal, &args_for(&blocks[9])).unwrap();
+        assert!(find_blocks_to_undo(&wal, seq, &target).is_err());
+
+        let (seq, target) = find_target(&wal, &args_for(&blocks[4])).unwrap();
+        assert_eq!(target, wal::ChainPoint::from(&blocks[4]));
+
+        // blocks undone by the rollback aren't undone twice
+        let to_undo = find_blocks_to_undo(&wal, seq, &target).unwrap();
+        let slots: Vec<_> = to_undo.iter().map(|x| x.slot).collect();
+        let expected: Vec<_> = blocks[5..9].iter().rev().map(|x| x.slot).collect();
+        assert_eq!(slots, expected);
+
+        undo_blocks(&ledger, &to_undo, cursor.0, &ProgressBar::hidden()).unwrap();
+
+        assert_eq!(
+            ledger.cursor().unwrap(),
+            Some(ledger::ChainPoint(blocks[4].slot, blocks[4].hash))
+        );
+
+        wal.truncate_after(&target).unwrap();
+
+        let (_, tip) = wal.find_tip().unwrap().unwrap();
+        assert_eq!(tip, target);
+    }
+}
@@ -0,0 +1,90 @@
+use dolos::ledger::{pparams, EraCbor};
+use itertools::Itertools;
+use miette::{Context, IntoDiagnostic};
+use pallas::ledger::traverse::MultiEraUpdate;
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// epoch boundary to simulate, the one after the last stored update if not
+    /// set
+    #[arg(long)]
+    epoch: Option<u64>,
+
+    /// protocol version to force at the boundary, to check a hardfork before
+    /// the update proposal exists on chain
+    #[arg(long)]
+    force_protocol: Option<usize>,
+}
+
+fn print_era(era: &pparams::EraSummary) {
+    println!(
+        "era start: epoch {}, slot {}, unix time {}",
+        era.start.epoch,
+        era.start.slot,
+        era.start.timestamp.timestamp()
+    );
+
+    println!("protocol: {}", era.pparams.protocol_version());
+}
+
+pub fn run(config: &crate::Config, args: &Args) -> miette::Result<()> {
+    crate::common::setup_tracing(&config.logging)?;
+
+    let (_, ledger) = crate::common::open_data_stores(config)?;
+    let genesis = crate::common::open_genesis_files(&config.genesis)?;
+
+    let tip = ledger
+        .cursor()
+        .into_diagnostic()
+        .context("reading ledger cursor")?;
+
+    let updates = ledger
+        .get_pparams(tip.map(|x| x.0).unwrap_or_default())
+        .into_diagnostic()
+        .context("retrieving pparams")?;
+
+    let updates: Vec<_> = updates
+        .iter()
+        .map(|EraCbor(era, cbor)| -> miette::Result<MultiEraUpdate> {
+            MultiEraUpdate::decode_for_era(*era, cbor).into_diagnostic()
+        })
+        .try_collect()?;
+
+    let boundary = args
+        .epoch
+        .unwrap_or_else(|| updates.iter().map(|x| x.epoch() + 1).max().unwrap_or(1));
+
+    // updates proposed during an epoch take effect at the start of the next one
+    let updates: Vec<_> = updates
+        .into_iter()
+        .filter(|x| x.epoch() < boundary)
+        .collect();
+
+    // nothing here touches the stores, the summary only lives in memory
+    let summary = pparams::fold(&genesis, &updates)
+        .into_diagnostic()
+        .context("processing pparams updates")?;
+
+    println!("simulating boundary of epoch {boundary}");
+
+    let before = summary.pparams_at(boundary.saturating_sub(1));
+    let after = summary.era_for_epoch(boundary);
+
+    println!(
+        "protocol before the boundary: {}",
+        before.protocol_version()
+    );
+    print_era(after);
+
+    let pparams = match args.force_protocol {
+        Some(protocol) => summary
+            .simulate_hardfork(boundary, protocol, &genesis)
+            .into_diagnostic()
+            .context("simulating hardfork")?,
+        None => after.pparams.clone(),
+    };
+
+    println!("resulting pparams: {pparams:#?}");
+
+    Ok(())
+}
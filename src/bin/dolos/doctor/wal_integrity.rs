@@ -1,43 +1,216 @@
-use dolos::wal::{self, RawBlock, ReadUtils, WalReader as _};
-use indicatif::{MultiProgress, ProgressBar, ProgressStyle};
-use miette::{Context, IntoDiagnostic};
-use pallas::ledger::traverse::MultiEraBlock;
-
-struct Feedback {
-    _multi: MultiProgress,
-    global_pb: ProgressBar,
+use dolos::{
+    ledger,
+    wal::{
+        self, BlockSlot, IntegrityCheck, IntegrityIssue, LogSeq, LogValue, RawBlock, WalReader as _,
+    },
+};
+use itertools::Itertools;
+use miette::{bail, Context, IntoDiagnostic};
+use std::path::{Path, PathBuf};
+use tokio_util::sync::CancellationToken;
+
+use crate::feedback::{Feedback, ProgressBar};
+
+#[derive(Debug, clap::Args)]
+pub struct Args {
+    /// start verifying from this slot
+    #[arg(long)]
+    from_slot: Option<BlockSlot>,
+
+    /// stop verifying at this slot
+    #[arg(long)]
+    to_slot: Option<BlockSlot>,
+
+    /// continue from the checkpoint left by an interrupted run
+    #[arg(long, action, conflicts_with = "from_slot")]
+    resume: bool,
+
+    /// remove the WAL entries from the first issue onwards, so that they're
+    /// fetched again from upstream on the next sync
+    #[arg(long, action)]
+    repair: bool,
 }
 
-impl Default for Feedback {
-    fn default() -> Self {
-        let multi = MultiProgress::new();
-
-        let global_pb = ProgressBar::new_spinner();
-        let global_pb = multi.add(global_pb);
-        global_pb.set_style(
-            ProgressStyle::with_template(
-                "{spinner:.green} [{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} (eta: {eta}) {msg}",
-            )
-            .unwrap()
-            .progress_chars("#>-"),
-        );
+fn log_slot(log: &LogValue) -> Option<BlockSlot> {
+    match log {
+        LogValue::Apply(RawBlock { slot, .. }) => Some(*slot),
+        LogValue::Undo(RawBlock { slot, .. }) => Some(*slot),
+        LogValue::Mark(wal::ChainPoint::Specific(slot, _)) => Some(*slot),
+        LogValue::Mark(wal::ChainPoint::Origin) => None,
+    }
+}
+
+fn issue_kind(issue: &IntegrityIssue) -> &'static str {
+    match issue {
+        IntegrityIssue::DecodeError(..) => "decode error",
+        IntegrityIssue::HashMismatch { .. } => "hash mismatch",
+        IntegrityIssue::SlotMismatch { .. } => "slot mismatch",
+        IntegrityIssue::BrokenChain { .. } => "broken chain",
+        IntegrityIssue::NonMonotonicNumber { .. } => "non-monotonic number",
+    }
+}
+
+fn checkpoint_path(config: &crate::Config) -> PathBuf {
+    config.storage.path.join("wal-integrity.checkpoint")
+}
+
+fn read_checkpoint(path: &Path) -> miette::Result<LogSeq> {
+    let content = std::fs::read_to_string(path)
+        .into_diagnostic()
+        .context("reading checkpoint, there's no interrupted run to resume")?;
+
+    content
+        .trim()
+        .parse()
+        .into_diagnostic()
+        .context("parsing checkpoint")
+}
+
+fn write_checkpoint(path: &Path, seq: LogSeq) -> miette::Result<()> {
+    std::fs::write(path, seq.to_string())
+        .into_diagnostic()
+        .context("writing checkpoint")
+}
+
+#[derive(Debug, Default)]
+struct Report {
+    verified: usize,
+    issues: Vec<IntegrityIssue>,
+    /// Sequence of the first entry with issues
+    first_bad: Option<LogSeq>,
+    /// Sequence of the last apply or mark entry checked before the first issue
+    last_good: Option<LogSeq>,
+    /// Sequence of the first entry left unchecked, set if the scan was
+    /// cancelled
+    interrupted_at: Option<LogSeq>,
+}
+
+/// Checks the given WAL entries until they're exhausted or the scan is
+/// cancelled
+fn verify(
+    entries: impl Iterator<Item = (LogSeq, LogValue)>,
+    progress: &ProgressBar,
+    cancel: &CancellationToken,
+) -> Report {
+    let mut check = IntegrityCheck::default();
+    let mut report = Report::default();
+
+    for (seq, log) in entries {
+        if cancel.is_cancelled() {
+            report.interrupted_at = Some(seq);
+            break;
+        }
 
-        Self {
-            _multi: multi,
-            global_pb,
+        if let LogValue::Apply(RawBlock { hash, .. }) = &log {
+            progress.set_message(format!("checking block {hash}"));
+            report.verified += 1;
+        }
+
+        let issues = check.check(&log);
+
+        if !issues.is_empty() && report.first_bad.is_none() {
+            report.first_bad = Some(seq);
+        }
+
+        for issue in issues {
+            progress.println(format!("{issue:?}"));
+            report.issues.push(issue);
+        }
+
+        // undos are left out, truncating right after them would leave the WAL in
+        // the middle of a rollback
+        if report.first_bad.is_none() && !matches!(log, LogValue::Undo(..)) {
+            report.last_good = Some(seq);
+        }
+
+        if let Some(slot) = log_slot(&log) {
+            progress.set_position(slot);
         }
     }
+
+    report
 }
 
-#[derive(Debug, clap::Args)]
-pub struct Args {}
+/// Removes the WAL entries after the last good one, returning the new tip
+fn repair(
+    wal: &mut wal::redb::WalStore,
+    report: &Report,
+) -> miette::Result<Option<wal::ChainPoint>> {
+    let Some(last_good) = report.last_good else {
+        bail!("no valid entry found before the first issue, the WAL can't be repaired");
+    };
+
+    wal.remove_range(Some(last_good + 1), None)
+        .into_diagnostic()
+        .context("removing corrupt entries")?;
+
+    let tip = wal
+        .find_tip()
+        .into_diagnostic()
+        .context("finding WAL tip")?
+        .map(|(_, x)| x);
+
+    Ok(tip)
+}
+
+/// Tells if the ledger has applied blocks that aren't in the WAL anymore
+fn ledger_is_ahead(
+    wal: &wal::redb::WalStore,
+    cursor: Option<ledger::ChainPoint>,
+    tip: Option<&wal::ChainPoint>,
+) -> miette::Result<bool> {
+    let Some(ledger::ChainPoint(slot, hash)) = cursor else {
+        return Ok(false);
+    };
+
+    let found = wal
+        .locate_point(&wal::ChainPoint::Specific(slot, hash))
+        .into_diagnostic()
+        .context("locating ledger cursor")?;
+
+    let ahead = match tip {
+        Some(wal::ChainPoint::Specific(tip, _)) => slot > *tip,
+        _ => true,
+    };
+
+    Ok(found.is_none() || ahead)
+}
+
+/// Cancels the scan on ctrl-c, so that a checkpoint can be written. Once
+/// cancelled, another ctrl-c exits the process right away.
+fn hook_cancel(runtime: &tokio::runtime::Runtime) -> CancellationToken {
+    let cancel = CancellationToken::new();
+    let cancel2 = cancel.clone();
+
+    runtime.spawn(async move {
+        while tokio::signal::ctrl_c().await.is_ok() {
+            if cancel2.is_cancelled() {
+                std::process::exit(130);
+            }
+
+            cancel2.cancel();
+        }
+    });
 
-pub fn run(config: &crate::Config, _args: &Args) -> miette::Result<()> {
+    cancel
+}
+
+pub fn run(config: &crate::Config, args: &Args, feedback: &Feedback) -> miette::Result<()> {
     //crate::common::setup_tracing(&config.logging)?;
 
-    let feedback = Feedback::default();
+    let (mut wal, ledger) =
+        crate::common::open_data_stores(config).context("opening data stores")?;
+
+    let runtime = tokio::runtime::Runtime::new()
+        .into_diagnostic()
+        .context("starting runtime")?;
+
+    let cancel = hook_cancel(&runtime);
+
+    let checkpoint = checkpoint_path(config);
 
-    let (wal, _) = crate::common::open_data_stores(config).context("opening data stores")?;
+    let progress = feedback.slot_progress_bar();
+    progress.set_message("checking wal");
 
     let (_, tip) = wal
         .find_tip()
@@ -45,46 +218,167 @@ pub fn run(config: &crate::Config, _args: &Args) -> miette::Result<()> {
         .context("finding WAL tip")?
         .ok_or(miette::miette!("no WAL tip found"))?;
 
-    match tip {
-        wal::ChainPoint::Origin => feedback.global_pb.set_length(0),
-        wal::ChainPoint::Specific(slot, _) => feedback.global_pb.set_length(slot),
+    match (tip, args.to_slot) {
+        (_, Some(slot)) => progress.set_length(slot),
+        (wal::ChainPoint::Origin, _) => progress.set_length(0),
+        (wal::ChainPoint::Specific(slot, _), _) => progress.set_length(slot),
     }
 
-    let remaining = wal
-        .crawl_from(None)
+    let since = match (args.resume, args.from_slot) {
+        (true, _) => Some(read_checkpoint(&checkpoint)?),
+        (false, Some(slot)) => wal
+            .approximate_slot(slot, slot..slot + 200)
+            .into_diagnostic()
+            .context("finding initial slot")?,
+        (false, None) => None,
+    };
+
+    let entries = wal
+        .crawl_from(since)
         .into_diagnostic()
         .context("crawling wal")?
-        .filter_forward()
-        .into_blocks()
-        .flatten();
+        .take_while(|(_, log)| match (log_slot(log), args.to_slot) {
+            (Some(slot), Some(to)) => slot <= to,
+            _ => true,
+        });
 
-    let mut last_hash = None;
+    let report = verify(entries, &progress, &cancel);
 
-    for block in remaining {
-        let RawBlock {
-            slot, hash, body, ..
-        } = block;
+    // from here on, ctrl-c exits right away
+    cancel.cancel();
 
-        feedback
-            .global_pb
-            .set_message(format!("checking block {hash}"));
+    progress.finish_and_clear();
 
-        let blockd = MultiEraBlock::decode(&body)
-            .into_diagnostic()
-            .context("decoding blocks")?;
+    println!("verified blocks: {}", report.verified);
 
-        if let Some(last) = last_hash {
-            if let Some(previous) = blockd.header().previous_hash() {
-                assert_eq!(previous, last);
-            }
+    match report.interrupted_at {
+        Some(seq) => {
+            write_checkpoint(&checkpoint, seq)?;
+            println!("interrupted, run again with --resume to continue from wal seq {seq}");
+        }
+        None if checkpoint.exists() => {
+            std::fs::remove_file(&checkpoint)
+                .into_diagnostic()
+                .context("removing checkpoint")?;
         }
+        None => (),
+    }
+
+    if report.issues.is_empty() {
+        println!("no integrity issues found in wal");
+        return Ok(());
+    }
 
-        last_hash = Some(hash);
+    for (kind, group) in report.issues.iter().into_group_map_by(|x| issue_kind(x)) {
+        println!("{kind}: {}", group.len());
+    }
 
-        feedback.global_pb.set_position(slot);
+    let first = report.issues.first().map(|x| x.point()).unwrap();
+    let last = report.issues.last().map(|x| x.point()).unwrap();
+
+    println!("affected range: {first:?} to {last:?}");
+
+    if !args.repair {
+        bail!(
+            "found {} integrity issues in wal, use --repair to remove them",
+            report.issues.len()
+        );
     }
 
-    println!("no integrity issues found in wal");
+    let tip = repair(&mut wal, &report)?;
+
+    println!("WAL truncated, new tip is {tip:?}");
+
+    let cursor = ledger
+        .cursor()
+        .into_diagnostic()
+        .context("finding ledger cursor")?;
+
+    if ledger_is_ahead(&wal, cursor, tip.as_ref())? {
+        // release the dbs so that the ledger can be re-created by the rebuild
+        drop(ledger);
+        drop(wal);
+
+        println!("ledger is ahead of the repaired WAL, rebuilding it");
+
+        super::run_rebuild_ledger(config, feedback)?;
+    }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use dolos::wal::{testing, WalWriter as _};
+
+    use super::*;
+
+    fn scan(wal: &wal::redb::WalStore, cancel: &CancellationToken) -> Report {
+        let entries = wal.crawl_from(None).unwrap();
+        verify(entries, &ProgressBar::hidden(), cancel)
+    }
+
+    #[test]
+    fn test_repair_truncated_block() {
+        let valid = testing::valid_dummy_block();
+
+        let mut truncated = valid.clone();
+        truncated.body.truncate(truncated.body.len() / 2);
+
+        let mut wal = testing::empty_db();
+        wal.roll_forward([valid.clone(), truncated.clone()].into_iter())
+            .unwrap();
+
+        let report = scan(&wal, &CancellationToken::new());
+
+        assert_eq!(report.verified, 2);
+        assert_eq!(
+            report.issues,
+            vec![IntegrityIssue::DecodeError(wal::ChainPoint::from(
+                &truncated
+            ))]
+        );
+        assert_eq!(report.first_bad, Some(2));
+        assert_eq!(report.last_good, Some(1));
+
+        let tip = repair(&mut wal, &report).unwrap();
+        assert_eq!(tip, Some(wal::ChainPoint::from(&valid)));
+
+        // the repaired WAL is clean
+        let report = scan(&wal, &CancellationToken::new());
+        assert!(report.issues.is_empty());
+    }
+
+    #[test]
+    fn test_resume_cancelled_scan() {
+        let wal = testing::db_with_dummy_blocks(5);
+
+        // cancel right after checking the second block
+        let cancel = CancellationToken::new();
+
+        let entries = wal.crawl_from(None).unwrap().inspect(|(seq, _)| {
+            if *seq == 2 {
+                cancel.cancel();
+            }
+        });
+
+        let report = verify(entries, &ProgressBar::hidden(), &cancel);
+
+        assert_eq!(report.verified, 2);
+        assert_eq!(report.interrupted_at, Some(3));
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("checkpoint");
+
+        write_checkpoint(&path, report.interrupted_at.unwrap()).unwrap();
+
+        // resuming checks the rest of the blocks
+        let since = read_checkpoint(&path).unwrap();
+        let entries = wal.crawl_from(Some(since)).unwrap();
+
+        let report = verify(entries, &ProgressBar::hidden(), &CancellationToken::new());
+
+        assert_eq!(report.verified, 3);
+        assert_eq!(report.interrupted_at, None);
+    }
+}
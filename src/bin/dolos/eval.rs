@@ -1,4 +1,4 @@
-use dolos::ledger::{EraCbor, TxoRef};
+use dolos::ledger::{fees, EraCbor, TxoRef};
 use itertools::*;
 use miette::{Context, IntoDiagnostic};
 use pallas::{
@@ -44,9 +44,12 @@ pub fn run(config: &super::Config, args: &Args) -> miette::Result<()> {
         .into_diagnostic()
         .context("decoding tx cbor")?;
 
+    // reference inputs are needed for validation and for the reference script
+    // fees, even if they aren't consumed
     let refs = tx
         .consumes()
         .iter()
+        .chain(tx.reference_inputs().iter())
         .map(|utxo| TxoRef(*utxo.hash(), utxo.index() as u32))
         .collect_vec();
 
@@ -58,6 +61,7 @@ pub fn run(config: &super::Config, args: &Args) -> miette::Result<()> {
     let genesis = crate::common::open_genesis_files(&config.genesis)?;
 
     let mut utxos2 = UTxOs::new();
+    let mut refscripts_size = 0;
 
     for (ref_, body) in resolved.iter() {
         let txin = pallas::ledger::primitives::byron::TxIn::Variant0(
@@ -72,6 +76,8 @@ pub fn run(config: &super::Config, args: &Args) -> miette::Result<()> {
             .into_diagnostic()
             .context("decoding utxo")?;
 
+        refscripts_size += fees::script_ref_size(&value);
+
         utxos2.insert(key, value);
     }
 
@@ -88,6 +94,8 @@ pub fn run(config: &super::Config, args: &Args) -> miette::Result<()> {
         .try_collect()?;
 
     let pparams = dolos::ledger::pparams::fold(&genesis, &updates)
+        .into_diagnostic()
+        .context("processing pparams updates")?
         .edge()
         .pparams
         .clone();
@@ -104,5 +112,16 @@ pub fn run(config: &super::Config, args: &Args) -> miette::Result<()> {
 
     validate_tx(&tx, 0, &context, &utxos2, &mut cert_state).unwrap();
 
+    if let Some(min_fee) = fees::min_fee(&tx, &context.prot_params, refscripts_size) {
+        let paid = tx.fee().unwrap_or_default();
+
+        println!("min fee: {min_fee}");
+        println!("paid fee: {paid}");
+
+        if paid < min_fee {
+            println!("fee is below the required minimum");
+        }
+    }
+
     Ok(())
 }
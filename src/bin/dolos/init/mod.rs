@@ -62,16 +62,22 @@ impl From<&KnownNetwork> for dolos::model::UpstreamConfig {
                 peer_address: "backbone.mainnet.cardanofoundation.org:3001".into(),
                 network_magic: 764824073,
                 is_testnet: false,
+                fallback_peers: vec![],
+                peer_timeout: None,
             },
             KnownNetwork::CardanoPreProd => dolos::model::UpstreamConfig {
                 peer_address: "preprod-node.world.dev.cardano.org:30000".into(),
                 network_magic: 1,
                 is_testnet: true,
+                fallback_peers: vec![],
+                peer_timeout: None,
             },
             KnownNetwork::CardanoPreview => dolos::model::UpstreamConfig {
                 peer_address: "preview-node.world.dev.cardano.org:30002".into(),
                 network_magic: 2,
                 is_testnet: true,
+                fallback_peers: vec![],
+                peer_timeout: None,
             },
             // KnownNetwork::CardanoSanchonet => todo!(),
         }
@@ -168,9 +174,13 @@ impl From<Option<u64>> for HistoryPrunningOptions {
 #[derive(Debug, Parser)]
 pub struct Args {
     /// Use one of the well-known networks
-    #[arg(long)]
+    #[arg(long, alias = "network")]
     known_network: Option<KnownNetwork>,
 
+    /// Write the genesis files included for the known network
+    #[arg(long)]
+    include_genesis: Option<bool>,
+
     /// Remote peer to use as source
     #[arg(long)]
     remote_peer: Option<String>,
@@ -190,6 +200,11 @@ pub struct Args {
     /// Enable relay operations
     #[arg(long)]
     enable_relay: Option<bool>,
+
+    /// Don't prompt for values, use the ones from args or the defaults, and
+    /// skip the bootstrap of the data
+    #[arg(long, action)]
+    non_interactive: bool,
 }
 
 type IncludeGenesisFiles = Option<KnownNetwork>;
@@ -229,6 +244,14 @@ impl ConfigEditor {
         self
     }
 
+    fn apply_include_genesis(mut self, value: Option<bool>) -> Self {
+        if let Some(false) = value {
+            self.1 = None;
+        }
+
+        self
+    }
+
     fn apply_remote_peer(mut self, value: Option<&String>) -> Self {
         if let Some(remote_peer) = value {
             remote_peer.clone_into(&mut self.0.upstream.peer_address)
@@ -301,6 +324,7 @@ impl ConfigEditor {
 
     fn fill_values_from_args(self, args: &Args) -> Self {
         self.apply_known_network(args.known_network.as_ref())
+            .apply_include_genesis(args.include_genesis)
             .apply_remote_peer(args.remote_peer.as_ref())
             .apply_history_pruning(args.max_wal_history.into())
             .apply_serve_grpc(args.serve_grpc)
@@ -393,10 +417,28 @@ impl ConfigEditor {
         Ok(self.apply_history_pruning(value))
     }
 
-    fn confirm_values(mut self) -> miette::Result<ConfigEditor> {
+    fn confirm_values(mut self, args: &Args) -> miette::Result<ConfigEditor> {
+        if args.non_interactive {
+            // without a network there are no genesis files to point the config to
+            if args.known_network.is_none() {
+                return Err(miette!(
+                    "--network is required when using --non-interactive"
+                ));
+            }
+
+            return Ok(self);
+        }
+
+        // values given as args have already been applied, don't ask for them again
+        if args.known_network.is_none() {
+            self = self.prompt_known_network()?;
+        }
+
+        if args.include_genesis.is_none() {
+            self = self.prompt_include_genesis()?;
+        }
+
         self = self
-            .prompt_known_network()?
-            .prompt_include_genesis()?
             .prompt_remote_peer()?
             .prompt_history_pruning()?
             .prompt_serve_grpc()?
@@ -406,9 +448,9 @@ impl ConfigEditor {
         Ok(self)
     }
 
-    fn include_genesis_files(self) -> miette::Result<Self> {
+    fn include_genesis_files(self, root: &Path) -> miette::Result<Self> {
         if let Some(network) = &self.1 {
-            include::save_genesis_configs(&PathBuf::from("./"), network)?;
+            include::save_genesis_configs(root, network)?;
         }
 
         Ok(self)
@@ -436,12 +478,18 @@ pub fn run(
         .map(|x| ConfigEditor(x, None))
         .unwrap_or_default()
         .fill_values_from_args(args)
-        .confirm_values()?
-        .include_genesis_files()?
+        .confirm_values(args)?
+        .include_genesis_files(&PathBuf::from("./"))?
         .save(&PathBuf::from("dolos.toml"))?;
 
     println!("config saved to dolos.toml");
 
+    if args.non_interactive {
+        println!("- run `dolos bootstrap` to initialize the data");
+        println!("- run `dolos daemon` to start the node");
+        return Ok(());
+    }
+
     let config = super::Config::new(&None)
         .into_diagnostic()
         .context("parsing configuration")?;
@@ -453,3 +501,62 @@ pub fn run(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn editor_for(args: &[&str]) -> miette::Result<ConfigEditor> {
+        let args = Args::try_parse_from(std::iter::once("init").chain(args.iter().copied()))
+            .into_diagnostic()?;
+
+        ConfigEditor::default()
+            .fill_values_from_args(&args)
+            .confirm_values(&args)
+    }
+
+    #[test]
+    fn test_non_interactive_known_network() {
+        let editor = editor_for(&["--network", "preprod", "--non-interactive"]).unwrap();
+
+        assert_eq!(editor.0.upstream.network_magic, 1);
+        assert!(editor.0.upstream.is_testnet);
+        assert!(matches!(editor.1, Some(KnownNetwork::CardanoPreProd)));
+
+        let root = tempfile::tempdir().unwrap();
+        editor.include_genesis_files(root.path()).unwrap();
+
+        for name in ["byron.json", "shelley.json", "alonzo.json", "conway.json"] {
+            let path = root.path().join(name);
+            assert!(path.exists(), "{name} not written");
+
+            // the bundled files are the ones the config points to
+            let contents = std::fs::read(path).unwrap();
+            serde_json::from_slice::<serde_json::Value>(&contents).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_non_interactive_without_genesis() {
+        let editor = editor_for(&[
+            "--network",
+            "mainnet",
+            "--include-genesis",
+            "false",
+            "--non-interactive",
+        ])
+        .unwrap();
+
+        assert_eq!(editor.0.upstream.network_magic, 764824073);
+        assert!(editor.1.is_none());
+
+        let root = tempfile::tempdir().unwrap();
+        editor.include_genesis_files(root.path()).unwrap();
+        assert_eq!(std::fs::read_dir(root.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_non_interactive_requires_network() {
+        assert!(editor_for(&["--non-interactive"]).is_err());
+    }
+}
@@ -1,4 +1,5 @@
 use clap::{Parser, Subcommand};
+use dolos::ledger::pparams::CostModelTolerance;
 use miette::{Context, IntoDiagnostic, Result};
 use serde::{Deserialize, Serialize};
 use serde_with::{serde_as, DisplayFromStr};
@@ -75,6 +76,13 @@ pub struct StorageConfig {
 
     /// Maximum number of slots (not blocks) to keep in the WAL
     max_wal_history: Option<u64>,
+
+    /// Maximum number of rollbacks to keep in the rollback log
+    max_rollbacks: Option<u64>,
+
+    /// Trade-off between durability and write throughput of the stores
+    #[serde(default)]
+    durability: dolos::model::DurabilityMode,
 }
 
 impl Default for StorageConfig {
@@ -84,6 +92,8 @@ impl Default for StorageConfig {
             wal_cache: None,
             ledger_cache: None,
             max_wal_history: None,
+            max_rollbacks: None,
+            durability: Default::default(),
         }
     }
 }
@@ -96,6 +106,9 @@ pub struct GenesisConfig {
     alonzo_path: PathBuf,
     conway_path: PathBuf,
     force_protocol: Option<usize>,
+
+    #[serde(default)]
+    cost_model_tolerance: CostModelTolerance,
 }
 
 impl Default for GenesisConfig {
@@ -106,6 +119,7 @@ impl Default for GenesisConfig {
             alonzo_path: PathBuf::from("alonzo.json"),
             conway_path: PathBuf::from("conway.json"),
             force_protocol: None,
+            cost_model_tolerance: Default::default(),
         }
     }
 }
@@ -1,7 +1,7 @@
 use std::sync::Arc;
 
 use log::warn;
-use miette::Context;
+use miette::{Context, IntoDiagnostic};
 
 #[derive(Debug, clap::Args)]
 pub struct Args {}
@@ -12,7 +12,12 @@ pub async fn run(config: super::Config, _args: &Args) -> miette::Result<()> {
 
     let (wal, ledger) = crate::common::open_data_stores(&config)?;
     let genesis = Arc::new(crate::common::open_genesis_files(&config.genesis)?);
-    let mempool = dolos::mempool::Mempool::new(genesis.clone(), ledger.clone());
+
+    dolos::sync::reconcile::reconcile_on_startup(&wal, &ledger, &genesis)
+        .into_diagnostic()
+        .context("reconciling ledger with wal")?;
+
+    let mempool = dolos::mempool::Mempool::new(genesis.clone(), ledger.clone(), &config.submit);
     let exit = crate::common::hook_exit_token();
 
     dolos::serve::serve(config.serve, genesis, wal, ledger, mempool, exit)
@@ -14,11 +14,17 @@ pub fn run(config: &super::Config, args: &Args) -> miette::Result<()> {
 
     let (wal, ledger) = crate::common::open_data_stores(config)?;
     let genesis = Arc::new(crate::common::open_genesis_files(&config.genesis)?);
-    let mempool = dolos::mempool::Mempool::new(genesis.clone(), ledger.clone());
+
+    dolos::sync::reconcile::reconcile_on_startup(&wal, &ledger, &genesis)
+        .into_diagnostic()
+        .context("reconciling ledger with wal")?;
+
+    let mempool = dolos::mempool::Mempool::new(genesis.clone(), ledger.clone(), &config.submit);
 
     let sync = dolos::sync::pipeline(
         &config.sync,
         &config.upstream,
+        &config.submit,
         wal,
         ledger,
         genesis,
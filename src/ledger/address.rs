@@ -0,0 +1,108 @@
+use pallas::ledger::addresses::{Address, ShelleyDelegationPart};
+
+/// The parts of an address that are relevant for indexing and querying
+///
+/// Byron addresses don't have a payment / stake split, so they can only be
+/// matched by their full bytes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AddressKind {
+    Shelley {
+        payment: Vec<u8>,
+        stake: Option<Vec<u8>>,
+    },
+    Stake(Vec<u8>),
+    Byron,
+}
+
+/// Classifies an address into the parts used to index and query UTxOs
+///
+/// Enterprise addresses (those with a null delegation part) don't carry a
+/// stake part, in which case `stake` is `None`.
+pub fn classify_address(address: &Address) -> AddressKind {
+    match address {
+        Address::Shelley(x) => {
+            let stake = match x.delegation() {
+                ShelleyDelegationPart::Null => None,
+                other => Some(other.to_vec()),
+            };
+
+            AddressKind::Shelley {
+                payment: x.payment().to_vec(),
+                stake,
+            }
+        }
+        Address::Stake(x) => AddressKind::Stake(x.to_vec()),
+        Address::Byron(_) => AddressKind::Byron,
+    }
+}
+
+impl AddressKind {
+    /// The payment part of the address, if any
+    pub fn payment(&self) -> Option<&[u8]> {
+        match self {
+            AddressKind::Shelley { payment, .. } => Some(payment),
+            _ => None,
+        }
+    }
+
+    /// The stake part of the address, if any
+    pub fn stake(&self) -> Option<&[u8]> {
+        match self {
+            AddressKind::Shelley { stake, .. } => stake.as_deref(),
+            AddressKind::Stake(x) => Some(x),
+            AddressKind::Byron => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+
+    const PAYMENT_HASH: &str = "9493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8e";
+
+    #[test]
+    fn test_base_address() {
+        let address = Address::from_str("addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x").unwrap();
+
+        let kind = classify_address(&address);
+
+        assert_eq!(
+            kind.payment().map(hex::encode).as_deref(),
+            Some(PAYMENT_HASH)
+        );
+        assert!(kind.stake().is_some());
+    }
+
+    #[test]
+    fn test_enterprise_address() {
+        let address =
+            Address::from_str("addr1vx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzers66hrl8")
+                .unwrap();
+
+        let kind = classify_address(&address);
+
+        assert_eq!(
+            kind.payment().map(hex::encode).as_deref(),
+            Some(PAYMENT_HASH)
+        );
+
+        // enterprise addresses shouldn't produce an empty stake part
+        assert_eq!(kind.stake(), None);
+    }
+
+    #[test]
+    fn test_byron_address() {
+        let address =
+            Address::from_str("Ae2tdPwUPEZFRbyhz3cpfC2CumGzNkFBN2L42rcUc2yjQpEkxDbkPodpMAi")
+                .unwrap();
+
+        let kind = classify_address(&address);
+
+        assert_eq!(kind, AddressKind::Byron);
+        assert_eq!(kind.payment(), None);
+        assert_eq!(kind.stake(), None);
+    }
+}
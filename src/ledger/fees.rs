@@ -0,0 +1,432 @@
+//! Fee and min-utxo calculations driven by the protocol parameters of each era
+
+use pallas::{
+    applying::utils::MultiEraProtocolParameters,
+    codec::utils::{KeepRaw, Nullable},
+    ledger::{
+        primitives::conway::{PseudoScript, RationalNumber},
+        traverse::{MultiEraOutput, MultiEraTx},
+    },
+};
+
+/// Size of each tier of reference scripts, in bytes
+pub const REFSCRIPT_TIER_SIZE: u64 = 25_600;
+
+/// Factor (as a ratio) by which the price per byte grows on each tier
+const REFSCRIPT_TIER_MULTIPLIER: (u128, u128) = (6, 5);
+
+/// Fixed overhead, in bytes, that Babbage onwards adds to each output
+const UTXO_ENTRY_OVERHEAD: u64 = 160;
+
+/// Size, in words, of an Alonzo utxo entry without accounting for its value
+const UTXO_ENTRY_SIZE_WITHOUT_VAL: u64 = 27;
+
+/// Size, in words, of a datum hash within an Alonzo utxo entry
+const DATA_HASH_SIZE: u64 = 10;
+
+/// Size, in words, of an ada-only value
+const COIN_SIZE: u64 = 2;
+
+/// Cost of the linear component of the fee: `a * size + b`
+pub fn linear_fee(minfee_a: u64, minfee_b: u64, tx_size: u64) -> u64 {
+    minfee_a * tx_size + minfee_b
+}
+
+/// Cost of the execution units, rounded up as a whole
+pub fn ex_units_fee(
+    mem: u64,
+    steps: u64,
+    mem_price: &RationalNumber,
+    step_price: &RationalNumber,
+) -> u64 {
+    let (mn, md) = (mem_price.numerator as u128, mem_price.denominator as u128);
+    let (sn, sd) = (step_price.numerator as u128, step_price.denominator as u128);
+
+    let num = mem as u128 * mn * sd + steps as u128 * sn * md;
+
+    num.div_ceil(md * sd) as u64
+}
+
+/// Fee for the size of the reference scripts used by a tx
+///
+/// The price per byte starts at `cost_per_byte` and is multiplied by 1.2 for
+/// every [`REFSCRIPT_TIER_SIZE`] bytes. The result is rounded down once at the
+/// end, as the ledger does.
+pub fn refscript_fee(cost_per_byte: &RationalNumber, size: u64) -> u64 {
+    let (mul_num, mul_den) = REFSCRIPT_TIER_MULTIPLIER;
+
+    let mut price_num = cost_per_byte.numerator as u128;
+    let mut den = cost_per_byte.denominator as u128;
+    let mut acc = 0u128;
+    let mut remaining = size as u128;
+
+    while remaining > REFSCRIPT_TIER_SIZE as u128 {
+        acc += REFSCRIPT_TIER_SIZE as u128 * price_num;
+        remaining -= REFSCRIPT_TIER_SIZE as u128;
+
+        // move the accumulator to the denominator of the next tier
+        acc *= mul_den;
+        price_num *= mul_num;
+        den *= mul_den;
+    }
+
+    acc += remaining * price_num;
+
+    (acc / den) as u64
+}
+
+/// Min lovelace for an output of the given serialized size (Babbage onwards)
+pub fn babbage_min_utxo(coins_per_byte: u64, output_size: u64) -> u64 {
+    coins_per_byte * (UTXO_ENTRY_OVERHEAD + output_size)
+}
+
+/// Min lovelace for an Alonzo output whose value takes `value_words`
+pub fn alonzo_min_utxo(coins_per_word: u64, value_words: u64, has_datum_hash: bool) -> u64 {
+    let datum = if has_datum_hash { DATA_HASH_SIZE } else { 0 };
+
+    coins_per_word * (UTXO_ENTRY_SIZE_WITHOUT_VAL + value_words + datum)
+}
+
+/// Size in words of a value, following the Mary rules
+///
+/// `assets` holds the number of policies, the number of assets and the sum of
+/// the lengths of their names.
+pub fn mary_value_words(assets: Option<(u64, u64, u64)>) -> u64 {
+    match assets {
+        None => COIN_SIZE,
+        Some((policies, assets, name_bytes)) => {
+            let bytes = assets * 12 + name_bytes + policies * 28;
+            6 + bytes.div_ceil(8)
+        }
+    }
+}
+
+/// Size of the reference script held by an output, if any
+pub fn script_ref_size(output: &MultiEraOutput) -> u64 {
+    let size = match output.script_ref() {
+        Some(PseudoScript::NativeScript(x)) => x.raw_cbor().len(),
+        Some(PseudoScript::PlutusV1Script(x)) => x.as_ref().len(),
+        Some(PseudoScript::PlutusV2Script(x)) => x.as_ref().len(),
+        Some(PseudoScript::PlutusV3Script(x)) => x.as_ref().len(),
+        None => 0,
+    };
+
+    size as u64
+}
+
+/// Size of a tx as measured by the ledger to compute its fee
+///
+/// The ledger measures the `[body, witnesses, auxiliary data]` tuple, which
+/// leaves out the validity flag added by Alonzo. Byron txs keep their encoded
+/// size.
+pub fn tx_size(tx: &MultiEraTx) -> u64 {
+    fn tuple_size<A>(body: &[u8], witnesses: &[u8], aux: &Nullable<KeepRaw<'_, A>>) -> u64 {
+        let aux = match aux {
+            Nullable::Some(x) => x.raw_cbor().len(),
+            // missing auxiliary data is encoded as a null
+            _ => 1,
+        };
+
+        // one byte for the header of the array
+        (1 + body.len() + witnesses.len() + aux) as u64
+    }
+
+    if let Some(x) = tx.as_alonzo() {
+        tuple_size(
+            x.transaction_body.raw_cbor(),
+            x.transaction_witness_set.raw_cbor(),
+            &x.auxiliary_data,
+        )
+    } else if let Some(x) = tx.as_babbage() {
+        tuple_size(
+            x.transaction_body.raw_cbor(),
+            x.transaction_witness_set.raw_cbor(),
+            &x.auxiliary_data,
+        )
+    } else if let Some(x) = tx.as_conway() {
+        tuple_size(
+            x.transaction_body.raw_cbor(),
+            x.transaction_witness_set.raw_cbor(),
+            &x.auxiliary_data,
+        )
+    } else {
+        tx.size() as u64
+    }
+}
+
+/// Min fee required by the protocol for a tx
+///
+/// `refscripts_size` is the total size of the reference scripts of the utxos
+/// spent or referenced by the tx, which only counts from Conway onwards.
+/// Returns `None` for Byron, which uses a different fee policy, and for eras
+/// this version doesn't know about.
+pub fn min_fee(
+    tx: &MultiEraTx,
+    pparams: &MultiEraProtocolParameters,
+    refscripts_size: u64,
+) -> Option<u64> {
+    let size = tx_size(tx);
+
+    let (mem, steps) = tx
+        .redeemers()
+        .iter()
+        .map(|x| x.ex_units())
+        .fold((0, 0), |(mem, steps), x| (mem + x.mem, steps + x.steps));
+
+    let fee = match pparams {
+        MultiEraProtocolParameters::Byron(_) => return None,
+        MultiEraProtocolParameters::Shelley(x) => {
+            linear_fee(x.minfee_a.into(), x.minfee_b.into(), size)
+        }
+        MultiEraProtocolParameters::Alonzo(x) => {
+            let prices = &x.execution_costs;
+
+            linear_fee(x.minfee_a.into(), x.minfee_b.into(), size)
+                + ex_units_fee(mem, steps, &prices.mem_price, &prices.step_price)
+        }
+        MultiEraProtocolParameters::Babbage(x) => {
+            let prices = &x.execution_costs;
+
+            linear_fee(x.minfee_a.into(), x.minfee_b.into(), size)
+                + ex_units_fee(mem, steps, &prices.mem_price, &prices.step_price)
+        }
+        MultiEraProtocolParameters::Conway(x) => {
+            let prices = &x.execution_costs;
+
+            linear_fee(x.minfee_a.into(), x.minfee_b.into(), size)
+                + ex_units_fee(mem, steps, &prices.mem_price, &prices.step_price)
+                + refscript_fee(&x.minfee_refscript_cost_per_byte, refscripts_size)
+        }
+        _ => return None,
+    };
+
+    Some(fee)
+}
+
+/// Min lovelace an output must hold under the given protocol parameters
+///
+/// Returns `None` for eras before Alonzo, which used a fixed min utxo value,
+/// and for eras this version doesn't know about.
+pub fn min_utxo(output: &MultiEraOutput, pparams: &MultiEraProtocolParameters) -> Option<u64> {
+    match pparams {
+        MultiEraProtocolParameters::Byron(_) | MultiEraProtocolParameters::Shelley(_) => None,
+        MultiEraProtocolParameters::Alonzo(x) => {
+            let assets = output.value().assets();
+
+            let assets = (!assets.is_empty()).then(|| {
+                let policies = assets.len() as u64;
+                let all = assets.iter().flat_map(|x| x.assets());
+
+                let (count, names) = all.fold((0, 0), |(count, names), x| {
+                    (count + 1, names + x.name().len() as u64)
+                });
+
+                (policies, count, names)
+            });
+
+            let has_datum_hash = output.datum().is_some();
+
+            Some(alonzo_min_utxo(
+                x.ada_per_utxo_byte,
+                mary_value_words(assets),
+                has_datum_hash,
+            ))
+        }
+        MultiEraProtocolParameters::Babbage(x) => Some(babbage_min_utxo(
+            x.ada_per_utxo_byte,
+            output.encode().len() as u64,
+        )),
+        MultiEraProtocolParameters::Conway(x) => Some(babbage_min_utxo(
+            x.ada_per_utxo_byte,
+            output.encode().len() as u64,
+        )),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas::ledger::traverse::MultiEraBlock;
+
+    use super::*;
+    use crate::ledger::pparams::{self, Genesis};
+
+    const MAINNET_TEST_DATA: &str = "src/ledger/pparams/test_data/mainnet";
+
+    fn load_json<T>(path: &str) -> T
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let file = std::fs::File::open(path).unwrap();
+        serde_json::from_reader(file).unwrap()
+    }
+
+    fn load_block(hash: &str) -> Vec<u8> {
+        std::fs::read(format!(
+            "{MAINNET_TEST_DATA}/update_proposal_blocks/{hash}.cbor"
+        ))
+        .unwrap()
+    }
+
+    /// Folds the mainnet update proposals found in the test data
+    fn mainnet_summary() -> pparams::ChainSummary {
+        let genesis = Genesis {
+            byron: load_json(&format!("{MAINNET_TEST_DATA}/genesis/byron_genesis.json")),
+            shelley: load_json(&format!("{MAINNET_TEST_DATA}/genesis/shelley_genesis.json")),
+            alonzo: load_json(&format!("{MAINNET_TEST_DATA}/genesis/alonzo_genesis.json")),
+            conway: load_json(&format!("{MAINNET_TEST_DATA}/genesis/conway_genesis.json")),
+            force_protocol: None,
+            cost_model_tolerance: Default::default(),
+        };
+
+        let raw: Vec<_> = std::fs::read_dir(format!("{MAINNET_TEST_DATA}/update_proposal_blocks"))
+            .unwrap()
+            .map(|x| std::fs::read(x.unwrap().path()).unwrap())
+            .collect();
+
+        let mut blocks: Vec<_> = raw
+            .iter()
+            .map(|x| MultiEraBlock::decode(x).unwrap())
+            .collect();
+
+        blocks.sort_by_key(|x| x.slot());
+
+        // updates borrow from the txs, so these need to outlive them
+        let txs: Vec<_> = blocks.iter().map(|x| x.txs()).collect();
+
+        let updates: Vec<_> = blocks
+            .iter()
+            .zip(txs.iter())
+            .flat_map(|(block, txs)| {
+                let txs = txs.iter().filter_map(MultiEraTx::update);
+                txs.chain(block.update())
+            })
+            .collect();
+
+        pparams::fold(&genesis, &updates).unwrap()
+    }
+
+    fn ratio(numerator: u64, denominator: u64) -> RationalNumber {
+        RationalNumber {
+            numerator,
+            denominator,
+        }
+    }
+
+    #[test]
+    fn test_linear_fee() {
+        // mainnet values since the Shelley hardfork
+        assert_eq!(linear_fee(44, 155381, 0), 155381);
+        assert_eq!(linear_fee(44, 155381, 297), 168449);
+    }
+
+    #[test]
+    fn test_ex_units_fee() {
+        // mainnet prices since Alonzo
+        let mem_price = ratio(577, 10_000);
+        let step_price = ratio(721, 10_000_000);
+
+        assert_eq!(ex_units_fee(0, 0, &mem_price, &step_price), 0);
+
+        // 1_000_000 * 0.0577 + 400_000_000 * 0.0000721
+        assert_eq!(
+            ex_units_fee(1_000_000, 400_000_000, &mem_price, &step_price),
+            86540
+        );
+
+        // fractional results are rounded up
+        assert_eq!(ex_units_fee(1, 0, &mem_price, &step_price), 1);
+    }
+
+    #[test]
+    fn test_refscript_tiers() {
+        let cost = ratio(15, 1);
+
+        assert_eq!(refscript_fee(&cost, 0), 0);
+        assert_eq!(refscript_fee(&cost, 1), 15);
+
+        // exactly one tier is still charged at the base price
+        assert_eq!(refscript_fee(&cost, REFSCRIPT_TIER_SIZE), 384_000);
+
+        // the first byte of the second tier is charged at 15 * 1.2
+        assert_eq!(refscript_fee(&cost, REFSCRIPT_TIER_SIZE + 1), 384_018);
+
+        // two full tiers: 25600 * 15 + 25600 * 18
+        assert_eq!(refscript_fee(&cost, REFSCRIPT_TIER_SIZE * 2), 844_800);
+
+        // third tier at 15 * 1.44 = 21.6, rounded down at the end
+        assert_eq!(refscript_fee(&cost, REFSCRIPT_TIER_SIZE * 2 + 1), 844_821);
+        assert_eq!(refscript_fee(&cost, REFSCRIPT_TIER_SIZE * 2 + 5), 844_908);
+    }
+
+    #[test]
+    fn test_min_fee_of_mainnet_txs() {
+        let summary = mainnet_summary();
+
+        // txs that paid exactly the min fee: (block, tx index, fee)
+        let exact = [
+            // alonzo
+            (
+                "0822e72ec531fd72b74af75bbce83876547f538ca7ca9bc854d043aa888c478c",
+                17,
+                211_613,
+            ),
+            (
+                "11c08542cf8da1ab1d1686c259dfc922e492c8ae893bccb588059f62717e573a",
+                65,
+                373_929,
+            ),
+            // alonzo, with plutus scripts and auxiliary data
+            (
+                "da8dd783e7383951dd563aa75c308d1a109ecb4787df7e1ec980710613fddfa8",
+                6,
+                1_318_364,
+            ),
+            // babbage
+            (
+                "8d4eb9c1e090f3ed4f23ff3690a4ace2fb474cbfec15c420c88d9c3fe8fe3823",
+                6,
+                332_525,
+            ),
+        ];
+
+        for (block, idx, paid) in exact {
+            let cbor = load_block(block);
+            let block = MultiEraBlock::decode(&cbor).unwrap();
+            let tx = &block.txs()[idx];
+
+            assert_eq!(tx.fee(), Some(paid));
+
+            let pparams = &summary.era_for_slot(block.slot()).pparams;
+            assert_eq!(min_fee(tx, pparams, 0), Some(paid), "{}", tx.hash());
+        }
+
+        // no tx in those blocks paid less than the min fee
+        for (block, ..) in exact {
+            let cbor = load_block(block);
+            let block = MultiEraBlock::decode(&cbor).unwrap();
+            let pparams = &summary.era_for_slot(block.slot()).pparams;
+
+            for tx in block.txs() {
+                let min = min_fee(&tx, pparams, 0).unwrap();
+                assert!(min <= tx.fee().unwrap(), "{}", tx.hash());
+            }
+        }
+    }
+
+    #[test]
+    fn test_min_utxo() {
+        // ada-only output on Alonzo mainnet
+        assert_eq!(mary_value_words(None), 2);
+        assert_eq!(
+            alonzo_min_utxo(34482, mary_value_words(None), false),
+            999_978
+        );
+
+        // one policy with a single 32-byte asset name
+        assert_eq!(mary_value_words(Some((1, 1, 32))), 6 + 9);
+
+        // 65-byte ada-only output on Babbage mainnet
+        assert_eq!(babbage_min_utxo(4310, 65), 969_750);
+    }
+}
@@ -5,6 +5,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 
+pub mod address;
+pub mod fees;
 pub mod pparams;
 //pub mod validate;
 
@@ -74,9 +76,82 @@ impl From<&MultiEraInput<'_>> for TxoRef {
     }
 }
 
+impl std::fmt::Display for TxoRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}#{}", self.0, self.1)
+    }
+}
+
+/// Parses a txo ref in its canonical `{tx hash hex}#{output index}` form
+impl std::str::FromStr for TxoRef {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (hash, idx) = s.split_once('#').ok_or(ParseError::MissingSeparator('#'))?;
+
+        let hash = parse_hash(hash)?;
+        let idx = idx
+            .parse()
+            .map_err(|_| ParseError::InvalidIndex(idx.to_owned()))?;
+
+        Ok(TxoRef(hash, idx))
+    }
+}
+
 #[derive(Debug, Eq, PartialEq, Hash)]
 pub struct ChainPoint(pub BlockSlot, pub BlockHash);
 
+impl std::fmt::Display for ChainPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{},{}", self.0, self.1)
+    }
+}
+
+/// Parses a chain point in its canonical `{slot},{block hash hex}` form
+impl std::str::FromStr for ChainPoint {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (slot, hash) = s.split_once(',').ok_or(ParseError::MissingSeparator(','))?;
+
+        let slot = slot
+            .parse()
+            .map_err(|_| ParseError::InvalidSlot(slot.to_owned()))?;
+        let hash = parse_hash(hash)?;
+
+        Ok(ChainPoint(slot, hash))
+    }
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum ParseError {
+    #[error("missing '{0}' separator")]
+    MissingSeparator(char),
+
+    #[error("invalid slot '{0}'")]
+    InvalidSlot(String),
+
+    #[error("invalid output index '{0}'")]
+    InvalidIndex(String),
+
+    #[error("hash is not valid hex: {0}")]
+    InvalidHex(#[from] hex::FromHexError),
+
+    #[error("hash should be 32 bytes long, found {0}")]
+    InvalidHashLength(usize),
+}
+
+/// Parses a hex-encoded, 32 bytes long hash
+pub fn parse_hash(s: &str) -> Result<Hash<32>, ParseError> {
+    let bytes = hex::decode(s)?;
+
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|x: Vec<u8>| ParseError::InvalidHashLength(x.len()))?;
+
+    Ok(Hash::new(bytes))
+}
+
 pub type UtxoMap = HashMap<TxoRef, EraCbor>;
 
 pub type UtxoSet = HashSet<TxoRef>;
@@ -133,6 +208,9 @@ pub fn compute_delta(
 
     let txs: HashMap<_, _> = block.txs().into_iter().map(|tx| (tx.hash(), tx)).collect();
 
+    // txs that failed phase-2 validation only consume their collateral and
+    // produce their collateral return, which is what `consumes` and `produces`
+    // already yield for them. Any other effect must be skipped explicitly.
     for (tx_hash, tx) in txs.iter() {
         for (idx, produced) in tx.produces() {
             let uxto_ref = TxoRef(*tx_hash, idx as u32);
@@ -151,6 +229,10 @@ pub fn compute_delta(
             delta.consumed_utxo.insert(stxi_ref, stxi_body);
         }
 
+        if !tx.is_valid() {
+            continue;
+        }
+
         if let Some(update) = tx.update() {
             delta.new_pparams.push(EraCbor(tx.era(), update.encode()));
         }
@@ -374,4 +456,147 @@ mod tests {
 
         assert_eq!(apply.new_position, undo.undone_position);
     }
+
+    #[test]
+    fn test_invalid_tx() {
+        let mut cbor = load_test_block("alonzo27.block");
+
+        // flag the first tx as failed by replacing the empty list of invalid txs
+        // that closes the block
+        assert_eq!(cbor.pop(), Some(0x80));
+        cbor.extend([0x81, 0x00]);
+
+        let block = MultiEraBlock::decode(&cbor).unwrap();
+        let txs = block.txs();
+
+        let invalid = &txs[0];
+        assert!(!invalid.is_valid());
+
+        let context = fake_slice_for_block(&block);
+        let apply = super::compute_delta(&block, context.clone()).unwrap();
+
+        for (idx, _) in invalid.outputs().iter().enumerate() {
+            let utxo = TxoRef(invalid.hash(), idx as u32);
+            assert!(!apply.produced_utxo.contains_key(&utxo));
+        }
+
+        let consumed_by_valid: HashSet<_> = txs[1..]
+            .iter()
+            .flat_map(MultiEraTx::consumes)
+            .map(|x| TxoRef(*x.hash(), x.index() as u32))
+            .collect();
+
+        for input in invalid.inputs() {
+            let utxo = TxoRef(*input.hash(), input.index() as u32);
+
+            if !consumed_by_valid.contains(&utxo) {
+                assert!(!apply.consumed_utxo.contains_key(&utxo));
+            }
+        }
+
+        for input in invalid.collateral() {
+            let utxo = TxoRef(*input.hash(), input.index() as u32);
+            assert!(apply.consumed_utxo.contains_key(&utxo));
+        }
+
+        // undoing the block reverts exactly what was applied
+        let undo = super::compute_undo_delta(&block, context).unwrap();
+
+        let produced: HashSet<_> = apply.produced_utxo.keys().collect();
+        let undone: HashSet<_> = undo.undone_utxo.keys().collect();
+        assert_eq!(produced, undone);
+
+        let consumed: HashSet<_> = apply.consumed_utxo.keys().collect();
+        let recovered: HashSet<_> = undo.recovered_stxi.keys().collect();
+        assert_eq!(consumed, recovered);
+    }
+
+    #[test]
+    fn test_invalid_tx_update_proposal() {
+        // mainnet block with an update proposal in its third tx, from the pparams
+        // fixtures
+        let path = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("src/ledger/pparams/test_data/mainnet/update_proposal_blocks")
+            .join("8d378effcbbe98c9142371b934c32a3946628a2dfb05eee4472a40ec9d1ca862.cbor");
+
+        let mut cbor = std::fs::read(path).unwrap();
+
+        let block = MultiEraBlock::decode(&cbor).unwrap();
+        assert!(block.txs()[2].update().is_some());
+
+        let context = fake_slice_for_block(&block);
+        let apply = super::compute_delta(&block, context).unwrap();
+        assert_eq!(apply.new_pparams.len(), 1);
+
+        // flag the tx with the proposal as failed
+        assert_eq!(cbor.pop(), Some(0x80));
+        cbor.extend([0x81, 0x02]);
+
+        let block = MultiEraBlock::decode(&cbor).unwrap();
+        let txs = block.txs();
+
+        assert!(!txs[2].is_valid());
+        assert!(txs[2].update().is_some());
+
+        let context = fake_slice_for_block(&block);
+
+        let apply = super::compute_delta(&block, context.clone()).unwrap();
+        assert!(apply.new_pparams.is_empty());
+
+        let undo = super::compute_undo_delta(&block, context).unwrap();
+        assert!(undo.new_pparams.is_empty());
+    }
+
+    #[test]
+    fn test_text_roundtrip() {
+        for seed in [0u8, 1, 127, 255] {
+            let hash = Hash::new([seed; 32]);
+
+            let txo = TxoRef(hash, seed as u32 * 1000);
+            assert_eq!(txo.to_string().parse::<TxoRef>().unwrap(), txo);
+
+            let point = ChainPoint(seed as u64 * 1_000_000, hash);
+            assert_eq!(point.to_string().parse::<ChainPoint>().unwrap(), point);
+        }
+
+        let hex = "0ae3da29711600e94a33fb7441d2e76876a9a1e98b5ebdefbf2e3bc535617616";
+        let txo = TxoRef::from_str(&format!("{hex}#3")).unwrap();
+
+        assert_eq!(txo.to_string(), format!("{hex}#3"));
+    }
+
+    #[test]
+    fn test_text_malformed() {
+        let hex = "0ae3da29711600e94a33fb7441d2e76876a9a1e98b5ebdefbf2e3bc535617616";
+
+        assert_eq!(
+            TxoRef::from_str(hex),
+            Err(ParseError::MissingSeparator('#'))
+        );
+        assert_eq!(
+            TxoRef::from_str(&format!("{hex}#x")),
+            Err(ParseError::InvalidIndex("x".into()))
+        );
+        assert_eq!(
+            TxoRef::from_str(&format!("{}#0", &hex[..62])),
+            Err(ParseError::InvalidHashLength(31))
+        );
+        assert!(matches!(
+            TxoRef::from_str(&format!("{}zz#0", &hex[..62])),
+            Err(ParseError::InvalidHex(_))
+        ));
+
+        assert_eq!(
+            ChainPoint::from_str(hex),
+            Err(ParseError::MissingSeparator(','))
+        );
+        assert_eq!(
+            ChainPoint::from_str(&format!("-1,{hex}")),
+            Err(ParseError::InvalidSlot("-1".into()))
+        );
+        assert_eq!(
+            ChainPoint::from_str("10,"),
+            Err(ParseError::InvalidHashLength(0))
+        );
+    }
 }
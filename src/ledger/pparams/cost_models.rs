@@ -0,0 +1,227 @@
+use pallas::{
+    applying::utils::MultiEraProtocolParameters,
+    ledger::primitives::alonzo::{CostModel, Language as AlonzoLanguage},
+};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use super::PParamsError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlutusVersion {
+    V1,
+    V2,
+    V3,
+}
+
+impl std::fmt::Display for PlutusVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlutusVersion::V1 => write!(f, "PlutusV1"),
+            PlutusVersion::V2 => write!(f, "PlutusV2"),
+            PlutusVersion::V3 => write!(f, "PlutusV3"),
+        }
+    }
+}
+
+impl PlutusVersion {
+    /// Number of parameters of each of the cost model revisions we know about,
+    /// from oldest to newest
+    pub fn known_param_counts(&self) -> &'static [usize] {
+        match self {
+            PlutusVersion::V1 => &[166],
+            PlutusVersion::V2 => &[175, 185],
+            PlutusVersion::V3 => &[251, 297],
+        }
+    }
+}
+
+/// How to handle cost models that don't match any of the known revisions
+///
+/// The cost models are part of the script data hash of txs, so altering them
+/// makes the hashes we compute diverge from the chain. Adjusting them is only
+/// meant as a tolerance for evaluators that can't deal with unknown revisions.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CostModelTolerance {
+    /// Keep the models as found, warning about the unknown ones
+    #[default]
+    Keep,
+    /// Truncate or pad the unknown models to the closest known revision
+    Adjust,
+    /// Fail when an unknown model is found
+    Reject,
+}
+
+/// Checks the length of a cost model against the known revisions
+///
+/// Models matching a known revision are always left alone. What happens with
+/// the rest depends on the tolerance: they're kept as they are, adjusted or
+/// rejected. Adjusted models longer than a known revision have their extra
+/// parameters dropped, shorter ones are padded with the max value, same as the
+/// ledger does, so that the missing builtins can never be afforded.
+pub fn normalize_cost_model(
+    version: PlutusVersion,
+    model: &mut CostModel,
+    tolerance: CostModelTolerance,
+) -> Result<(), PParamsError> {
+    let known = version.known_param_counts();
+
+    if known.contains(&model.len()) {
+        return Ok(());
+    }
+
+    match tolerance {
+        CostModelTolerance::Keep => {
+            warn!(
+                %version,
+                found = model.len(),
+                expected = ?known,
+                "cost model doesn't match any known revision"
+            );
+
+            Ok(())
+        }
+        CostModelTolerance::Adjust => {
+            let target = known
+                .iter()
+                .rev()
+                .find(|x| **x <= model.len())
+                .unwrap_or(&known[0]);
+
+            warn!(
+                %version,
+                found = model.len(),
+                expected = target,
+                "adjusting cost model to a known revision"
+            );
+
+            model.resize(*target, i64::MAX);
+
+            Ok(())
+        }
+        CostModelTolerance::Reject => Err(PParamsError::InvalidCostModel {
+            version,
+            expected: known,
+            found: model.len(),
+        }),
+    }
+}
+
+fn normalize_optional(
+    version: PlutusVersion,
+    model: &mut Option<CostModel>,
+    tolerance: CostModelTolerance,
+) -> Result<(), PParamsError> {
+    match model {
+        Some(x) => normalize_cost_model(version, x, tolerance),
+        None => Ok(()),
+    }
+}
+
+/// Normalizes all of the cost models of the pparams, see
+/// [normalize_cost_model]
+pub fn normalize_cost_models(
+    pparams: &mut MultiEraProtocolParameters,
+    tolerance: CostModelTolerance,
+) -> Result<(), PParamsError> {
+    match pparams {
+        MultiEraProtocolParameters::Alonzo(x) => {
+            let mut models: Vec<_> = x.cost_models_for_script_languages.iter().cloned().collect();
+
+            for (language, model) in models.iter_mut() {
+                if *language == AlonzoLanguage::PlutusV1 {
+                    normalize_cost_model(PlutusVersion::V1, model, tolerance)?;
+                }
+            }
+
+            x.cost_models_for_script_languages = models.into();
+        }
+        MultiEraProtocolParameters::Babbage(x) => {
+            let models = &mut x.cost_models_for_script_languages;
+            normalize_optional(PlutusVersion::V1, &mut models.plutus_v1, tolerance)?;
+            normalize_optional(PlutusVersion::V2, &mut models.plutus_v2, tolerance)?;
+        }
+        MultiEraProtocolParameters::Conway(x) => {
+            let models = &mut x.cost_models_for_script_languages;
+            normalize_optional(PlutusVersion::V1, &mut models.plutus_v1, tolerance)?;
+            normalize_optional(PlutusVersion::V2, &mut models.plutus_v2, tolerance)?;
+            normalize_optional(PlutusVersion::V3, &mut models.plutus_v3, tolerance)?;
+        }
+        _ => (),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL_TOLERANCES: [CostModelTolerance; 3] = [
+        CostModelTolerance::Keep,
+        CostModelTolerance::Adjust,
+        CostModelTolerance::Reject,
+    ];
+
+    #[test]
+    fn test_known_lengths() {
+        for tolerance in ALL_TOLERANCES {
+            for version in [PlutusVersion::V1, PlutusVersion::V2, PlutusVersion::V3] {
+                for count in version.known_param_counts() {
+                    let mut model = vec![1; *count];
+                    normalize_cost_model(version, &mut model, tolerance).unwrap();
+                    assert_eq!(model.len(), *count);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unknown_lengths_are_kept() {
+        let mut model: CostModel = (0..260).collect();
+        normalize_cost_model(PlutusVersion::V3, &mut model, CostModelTolerance::Keep).unwrap();
+        assert_eq!(model, (0..260).collect::<CostModel>());
+
+        let mut model = vec![1; 100];
+        normalize_cost_model(PlutusVersion::V2, &mut model, CostModelTolerance::Keep).unwrap();
+        assert_eq!(model, vec![1; 100]);
+    }
+
+    #[test]
+    fn test_unknown_lengths_are_adjusted() {
+        let mut model: CostModel = (0..260).collect();
+        normalize_cost_model(PlutusVersion::V3, &mut model, CostModelTolerance::Adjust).unwrap();
+
+        assert_eq!(model.len(), 251);
+        assert_eq!(model.last(), Some(&250));
+
+        let mut model = vec![1; 100];
+        normalize_cost_model(PlutusVersion::V2, &mut model, CostModelTolerance::Adjust).unwrap();
+
+        assert_eq!(model.len(), 175);
+        assert_eq!(model[99], 1);
+        assert_eq!(model[100], i64::MAX);
+    }
+
+    #[test]
+    fn test_unknown_lengths_are_rejected() {
+        let mut model = vec![1; 100];
+        let result =
+            normalize_cost_model(PlutusVersion::V2, &mut model, CostModelTolerance::Reject);
+
+        assert!(matches!(
+            result,
+            Err(PParamsError::InvalidCostModel {
+                version: PlutusVersion::V2,
+                expected: [175, 185],
+                found: 100,
+            })
+        ));
+
+        // the error explains what was expected
+        let message = result.unwrap_err().to_string();
+        assert!(message.contains("PlutusV2"));
+        assert!(message.contains("175"));
+    }
+}
@@ -9,10 +9,13 @@ use pallas::{
         traverse::MultiEraUpdate,
     },
 };
+use thiserror::Error;
 use tracing::{debug, trace, warn};
 
+mod cost_models;
 mod summary;
 
+pub use cost_models::*;
 pub use summary::*;
 
 macro_rules! apply_field {
@@ -37,6 +40,33 @@ pub struct Genesis {
     pub alonzo: alonzo::GenesisFile,
     pub conway: conway::GenesisFile,
     pub force_protocol: Option<usize>,
+    pub cost_model_tolerance: CostModelTolerance,
+}
+
+#[derive(Debug, Error)]
+pub enum PParamsError {
+    #[error("this version cannot process the hardfork from protocol {from} to protocol {to}")]
+    UnsupportedHardfork { from: usize, to: usize },
+
+    #[error("update for epoch {epoch} is older than the edge era starting at epoch {edge}")]
+    PastEraUpdate { epoch: u64, edge: u64 },
+
+    #[error("{version} cost model has {found} parameters, expected one of {expected:?}")]
+    InvalidCostModel {
+        version: PlutusVersion,
+        expected: &'static [usize],
+        found: usize,
+    },
+}
+
+impl Genesis {
+    /// The network magic declared by the genesis files
+    pub fn network_magic(&self) -> u64 {
+        match self.shelley.network_magic {
+            Some(x) => x.into(),
+            None => self.byron.protocol_consts.protocol_magic.into(),
+        }
+    }
 }
 
 fn bootstrap_byron_pparams(byron: &byron::GenesisFile) -> ByronProtParams {
@@ -459,8 +489,8 @@ fn migrate_pparams(
     current: MultiEraProtocolParameters,
     genesis: &Genesis,
     next_protocol: usize,
-) -> MultiEraProtocolParameters {
-    match current {
+) -> Result<MultiEraProtocolParameters, PParamsError> {
+    let mut out = match current {
         // Source: https://github.com/cardano-foundation/CIPs/blob/master/CIP-0059/feature-table.md
         // NOTE: part of the confusion here is that there are two versioning schemes that can be
         // easily conflated:
@@ -503,23 +533,44 @@ fn migrate_pparams(
         MultiEraProtocolParameters::Babbage(current) if next_protocol == 9 => {
             MultiEraProtocolParameters::Conway(bootstrap_conway_pparams(current, &genesis.conway))
         }
-        x => unimplemented!(
-            "don't know how to handle hardfork (protocol: {})",
-            x.protocol_version()
-        ),
+        x => {
+            return Err(PParamsError::UnsupportedHardfork {
+                from: x.protocol_version(),
+                to: next_protocol,
+            })
+        }
+    };
+
+    normalize_cost_models(&mut out, genesis.cost_model_tolerance)?;
+
+    Ok(out)
+}
+
+/// Overrides the major protocol version of the pparams
+fn set_protocol_version(pparams: &mut MultiEraProtocolParameters, major: usize) {
+    match pparams {
+        MultiEraProtocolParameters::Byron(x) => x.block_version = (major as u16, 0, 0),
+        MultiEraProtocolParameters::Shelley(x) => x.protocol_version = (major as u64, 0),
+        MultiEraProtocolParameters::Alonzo(x) => x.protocol_version = (major as u64, 0),
+        MultiEraProtocolParameters::Babbage(x) => x.protocol_version = (major as u64, 0),
+        MultiEraProtocolParameters::Conway(x) => x.protocol_version = (major as u64, 0),
+        _ => (),
     }
 }
 
-pub fn fold(genesis: &Genesis, updates: &[MultiEraUpdate]) -> ChainSummary {
-    let mut summary = ChainSummary::start(genesis);
+/// Folds the updates on top of the genesis pparams, failing if any of them
+/// requires a hardfork this version can't process or carries a cost model
+/// rejected by the tolerance of the genesis
+pub fn fold(genesis: &Genesis, updates: &[MultiEraUpdate]) -> Result<ChainSummary, PParamsError> {
+    let mut summary = ChainSummary::start(genesis)?;
 
     updates.to_vec().sort_by_key(|u| u.epoch());
 
     for update in updates {
-        summary.apply_update(update, genesis);
+        summary.apply_update(update, genesis)?;
     }
 
-    summary
+    Ok(summary)
 }
 
 #[cfg(test)]
@@ -539,20 +590,23 @@ mod tests {
         serde_json::from_reader(file).unwrap()
     }
 
-    fn test_env_fold(env: &str) {
+    fn load_genesis(env: &str) -> Genesis {
         let test_data = format!("src/ledger/pparams/test_data/{env}");
 
-        // Load each genesis file
-        let genesis = Genesis {
+        Genesis {
             byron: load_json(format!("{test_data}/genesis/byron_genesis.json")),
             shelley: load_json(format!("{test_data}/genesis/shelley_genesis.json")),
             alonzo: load_json(format!("{test_data}/genesis/alonzo_genesis.json")),
             conway: load_json(format!("{test_data}/genesis/conway_genesis.json")),
             force_protocol: None,
-        };
+            cost_model_tolerance: Default::default(),
+        }
+    }
 
-        // Then load each mainnet example update proposal as buffers
-        let files: Vec<_> = std::fs::read_dir(format!("{test_data}/update_proposal_blocks/"))
+    fn load_update_proposal_blocks(env: &str) -> Vec<Vec<u8>> {
+        let test_data = format!("src/ledger/pparams/test_data/{env}");
+
+        std::fs::read_dir(format!("{test_data}/update_proposal_blocks/"))
             .unwrap()
             .map(|x| std::fs::File::open(x.unwrap().path()).unwrap())
             .map(|mut x| {
@@ -560,30 +614,44 @@ mod tests {
                 x.read_to_end(&mut buf).unwrap();
                 buf
             })
-            .collect();
+            .collect()
+    }
 
-        // Decode those buffers as blocks, and sort them by slot, so we can process them
-        // in order
-        let blocks: Vec<_> = files
+    fn decode_blocks(files: &[Vec<u8>]) -> Vec<MultiEraBlock<'_>> {
+        files
             .iter()
             .map(|x| MultiEraBlock::decode(x).unwrap())
             .sorted_by_key(|b| b.slot())
-            .collect();
-
-        let block_data: Vec<_> = blocks.iter().map(|b| (b.update(), b.txs())).collect();
+            .collect()
+    }
 
-        let update_pairs: Vec<_> = block_data
+    /// Flattens the updates of the blocks and their txs, in chain order
+    fn chain_updates<'a>(
+        block_data: &'a [(Option<MultiEraUpdate<'a>>, Vec<MultiEraTx<'a>>)],
+    ) -> Vec<MultiEraUpdate<'a>> {
+        block_data
             .iter()
-            .map(|(b, txs)| (b, txs.iter().filter_map(MultiEraTx::update)))
-            .collect();
-
-        let chained_updates: Vec<_> = update_pairs
-            .into_iter()
             .flat_map(|(b, txs)| {
                 let b = b.iter().cloned();
-                txs.chain(b)
+                txs.iter().filter_map(MultiEraTx::update).chain(b)
             })
-            .collect();
+            .collect()
+    }
+
+    fn test_env_fold(env: &str) {
+        let test_data = format!("src/ledger/pparams/test_data/{env}");
+
+        // Load each genesis file
+        let genesis = load_genesis(env);
+
+        // Then load each mainnet example update proposal as buffers
+        let files = load_update_proposal_blocks(env);
+
+        // Decode those buffers as blocks, and sort them by slot, so we can process them
+        // in order
+        let blocks = decode_blocks(&files);
+        let block_data: Vec<_> = blocks.iter().map(|b| (b.update(), b.txs())).collect();
+        let chained_updates = chain_updates(&block_data);
 
         // Now, for each epoch we've recorded protocol parameters for,
         // test if we get the right value when folding
@@ -606,12 +674,40 @@ mod tests {
 
             // TODO: implement serialize/deserialize, and get full protocol param json files
             let expected = load_json::<usize, _>(filename);
-            let summary = fold(&genesis, updates.as_slice());
+            let summary = fold(&genesis, updates.as_slice()).unwrap();
 
-            assert_eq!(expected, summary.edge().pparams.protocol_version())
+            assert_eq!(expected, summary.edge().pparams.protocol_version());
 
             //assert_eq!(expected, actual)
         }
+
+        // slots convert to time and back across every era boundary
+        let summary = fold(&genesis, &chained_updates).unwrap();
+
+        for era in summary.iter_eras() {
+            let slot_length = era.pparams.slot_length();
+            let start = era.start.slot;
+
+            assert_eq!(
+                summary.slot_to_time(start),
+                Some(era.start.timestamp.to_utc())
+            );
+
+            for slot in [start.saturating_sub(1), start, start + 1] {
+                let time = summary.slot_to_time(slot).unwrap();
+                assert_eq!(summary.time_to_slot(time), Some(slot));
+
+                // any instant within the slot maps back to it
+                let within = time + chrono::Duration::seconds(slot_length as i64 - 1);
+                assert_eq!(summary.time_to_slot(within), Some(slot));
+            }
+        }
+
+        let system_start = summary.slot_to_time(0).unwrap();
+        assert_eq!(
+            summary.time_to_slot(system_start - chrono::Duration::seconds(1)),
+            None
+        );
     }
 
     #[test]
@@ -619,6 +715,100 @@ mod tests {
         test_env_fold("mainnet")
     }
 
+    #[test]
+    fn test_mainnet_conway_hardfork() {
+        let genesis = load_genesis("mainnet");
+
+        let files = load_update_proposal_blocks("mainnet");
+        let blocks = decode_blocks(&files);
+        let block_data: Vec<_> = blocks.iter().map(|b| (b.update(), b.txs())).collect();
+        let updates = chain_updates(&block_data);
+
+        let mut summary = fold(&genesis, &updates).unwrap();
+        assert_eq!(summary.edge().pparams.protocol_version(), 8);
+
+        // mainnet moved to protocol 9 at the boundary of epoch 507
+        let simulated = summary.simulate_hardfork(507, 9, &genesis).unwrap();
+        assert_eq!(simulated.protocol_version(), 9);
+
+        // simulating doesn't change the summary
+        assert_eq!(summary.edge().pparams.protocol_version(), 8);
+
+        // values of the mainnet pparams in effect during epoch 507
+        let MultiEraProtocolParameters::Conway(x) = simulated else {
+            panic!("expected conway pparams");
+        };
+
+        assert_eq!(x.minfee_a, 44);
+        assert_eq!(x.minfee_b, 155381);
+        assert_eq!(x.max_block_body_size, 90112);
+        assert_eq!(x.max_transaction_size, 16384);
+        assert_eq!(x.max_block_header_size, 1100);
+        assert_eq!(x.key_deposit, 2_000_000);
+        assert_eq!(x.pool_deposit, 500_000_000);
+        assert_eq!(x.min_pool_cost, 170_000_000);
+        assert_eq!(x.ada_per_utxo_byte, 4310);
+        assert_eq!(x.execution_costs.mem_price.numerator, 577);
+        assert_eq!(x.execution_costs.mem_price.denominator, 10_000);
+        assert_eq!(x.execution_costs.step_price.numerator, 721);
+        assert_eq!(x.execution_costs.step_price.denominator, 10_000_000);
+        assert_eq!(x.max_tx_ex_units.mem, 14_000_000);
+        assert_eq!(x.max_tx_ex_units.steps, 10_000_000_000);
+        assert_eq!(x.max_block_ex_units.mem, 62_000_000);
+        assert_eq!(x.max_block_ex_units.steps, 20_000_000_000);
+        assert_eq!(x.max_value_size, 5000);
+        assert_eq!(x.collateral_percentage, 150);
+        assert_eq!(x.max_collateral_inputs, 3);
+
+        let cost_models = &x.cost_models_for_script_languages;
+        assert_eq!(cost_models.plutus_v1.as_ref().unwrap().len(), 166);
+        assert_eq!(cost_models.plutus_v2.as_ref().unwrap().len(), 175);
+        assert_eq!(cost_models.plutus_v3.as_ref().unwrap().len(), 251);
+
+        assert_eq!(x.drep_deposit, 500_000_000);
+        assert_eq!(x.drep_inactivity_period, 20);
+        assert_eq!(x.governance_action_deposit, 100_000_000_000);
+        assert_eq!(x.governance_action_validity_period, 6);
+        assert_eq!(x.min_committee_size, 7);
+        assert_eq!(x.committee_term_limit, 146);
+        assert_eq!(x.minfee_refscript_cost_per_byte.numerator, 15);
+        assert_eq!(x.minfee_refscript_cost_per_byte.denominator, 1);
+
+        // the era in effect at an earlier epoch is the one being forked
+        let earlier = summary.simulate_hardfork(300, 9, &genesis).unwrap();
+        let MultiEraProtocolParameters::Conway(x) = earlier else {
+            panic!("expected conway pparams");
+        };
+        assert_eq!(x.max_block_body_size, 65536);
+
+        // updates can't be applied on top of eras that already started
+        let result = summary.apply_update(&updates[0], &genesis);
+
+        assert!(matches!(
+            result,
+            Err(PParamsError::PastEraUpdate { edge: 445, .. })
+        ));
+    }
+
+    #[test]
+    fn test_unsupported_hardfork() {
+        let genesis = load_genesis("mainnet");
+        let summary = ChainSummary::start(&genesis).unwrap();
+
+        // every known hardfork is processed until the first unknown one
+        let result = summary.simulate_hardfork(0, 10, &genesis);
+
+        assert!(matches!(
+            result,
+            Err(PParamsError::UnsupportedHardfork { from: 9, to: 10 })
+        ));
+
+        let mut genesis = genesis;
+        genesis.force_protocol = Some(10);
+
+        assert!(ChainSummary::start(&genesis).is_err());
+    }
+
     #[test]
     fn test_pool_voting_thresholds_rational() {
         let thresholds = [
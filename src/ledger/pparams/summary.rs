@@ -1,7 +1,7 @@
 use pallas::{applying::MultiEraProtocolParameters, ledger::traverse::MultiEraUpdate};
 use tracing::debug;
 
-use super::Genesis;
+use super::{Genesis, PParamsError};
 
 #[derive(Clone, Debug)]
 pub struct EraBoundary {
@@ -24,19 +24,19 @@ pub struct ChainSummary {
 }
 
 impl ChainSummary {
-    pub fn start(genesis: &Genesis) -> Self {
+    pub fn start(genesis: &Genesis) -> Result<Self, PParamsError> {
         let mut pparams =
             MultiEraProtocolParameters::Byron(super::bootstrap_byron_pparams(&genesis.byron));
 
         if let Some(force_protocol) = genesis.force_protocol {
             for next_protocol in 1..=force_protocol {
-                pparams = super::migrate_pparams(pparams, genesis, next_protocol);
+                pparams = super::migrate_pparams(pparams, genesis, next_protocol)?;
 
                 debug!(protocol = next_protocol, "forced hardfork");
             }
         }
 
-        Self {
+        Ok(Self {
             past: vec![],
             edge: Some(EraSummary {
                 start: EraBoundary {
@@ -47,27 +47,56 @@ impl ChainSummary {
                 end: None,
                 pparams,
             }),
-        }
+        })
     }
 
-    pub fn apply_update(&mut self, update: &MultiEraUpdate, genesis: &Genesis) {
+    pub fn apply_update(
+        &mut self,
+        update: &MultiEraUpdate,
+        genesis: &Genesis,
+    ) -> Result<(), PParamsError> {
         let apply_epoch = update.epoch() + 1;
 
-        assert!(
-            apply_epoch >= self.edge().start.epoch,
-            "can't apply update for past era"
-        );
+        if apply_epoch < self.edge().start.epoch {
+            return Err(PParamsError::PastEraUpdate {
+                epoch: apply_epoch,
+                edge: self.edge().start.epoch,
+            });
+        }
 
         let mut pparams = super::apply_param_update(self.edge().pparams.clone(), update);
+        super::normalize_cost_models(&mut pparams, genesis.cost_model_tolerance)?;
 
         let next_version = pparams.protocol_version();
 
         if next_version > self.edge().pparams.protocol_version() {
-            pparams = super::migrate_pparams(pparams, genesis, next_version);
+            pparams = super::migrate_pparams(pparams, genesis, next_version)?;
             debug!(protocol = next_version, "hardfork executed");
         }
 
         self.advance(apply_epoch, pparams);
+
+        Ok(())
+    }
+
+    /// Computes the pparams that the era in effect at the given epoch would
+    /// transition to if the protocol was bumped to the given version, without
+    /// changing the summary
+    pub fn simulate_hardfork(
+        &self,
+        epoch: u64,
+        next_protocol: usize,
+        genesis: &Genesis,
+    ) -> Result<MultiEraProtocolParameters, PParamsError> {
+        let mut pparams = self.era_for_epoch(epoch).pparams.clone();
+        let current = pparams.protocol_version();
+
+        for protocol in (current + 1)..=next_protocol {
+            pparams = super::migrate_pparams(pparams, genesis, protocol)?;
+            super::set_protocol_version(&mut pparams, protocol);
+        }
+
+        Ok(pparams)
     }
 
     fn advance(&mut self, at_epoch: u64, pparams: MultiEraProtocolParameters) {
@@ -135,4 +164,45 @@ impl ChainSummary {
             .find(|e| slot >= e.start.slot && e.end.as_ref().unwrap().slot > slot)
             .unwrap()
     }
+
+    /// Iterate all the eras we know about, in chronological order
+    pub fn iter_eras(&self) -> impl Iterator<Item = &EraSummary> + '_ {
+        self.past.iter().chain(self.edge.iter())
+    }
+
+    /// Return the wall-clock time at which a given slot starts
+    ///
+    /// Each era is measured with its own slot length (eg: 20 secs for Byron, 1
+    /// sec for Shelley onwards). Slots past the edge era are assumed to keep
+    /// the slot length of the edge era.
+    pub fn slot_to_time(&self, slot: u64) -> Option<chrono::DateTime<chrono::Utc>> {
+        let era = self.era_for_slot(slot);
+
+        let seconds = (slot - era.start.slot).checked_mul(era.pparams.slot_length())?;
+        let time = era
+            .start
+            .timestamp
+            .checked_add_signed(chrono::Duration::seconds(seconds.try_into().ok()?))?;
+
+        Some(time.with_timezone(&chrono::Utc))
+    }
+
+    /// Return the slot that is in progress at a given wall-clock time
+    ///
+    /// Returns `None` if the time is before the start of the chain.
+    pub fn time_to_slot(&self, time: chrono::DateTime<chrono::Utc>) -> Option<u64> {
+        let era = self.iter_eras().find(|era| {
+            time >= era.start.timestamp
+                && !era.end.as_ref().is_some_and(|end| time >= end.timestamp)
+        })?;
+
+        let seconds = (time - era.start.timestamp).num_seconds() as u64;
+
+        Some(era.start.slot + seconds / era.pparams.slot_length())
+    }
+
+    /// Return the protocol parameters in effect for a given epoch
+    pub fn pparams_at(&self, epoch: u64) -> &MultiEraProtocolParameters {
+        &self.era_for_epoch(epoch).pparams
+    }
 }
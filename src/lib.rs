@@ -1,4 +1,5 @@
 pub mod ledger;
+pub mod lock;
 pub mod mempool;
 pub mod model;
 pub mod prelude;
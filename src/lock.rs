@@ -0,0 +1,102 @@
+use fs2::FileExt as _;
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read as _, Seek as _, Write as _},
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use thiserror::Error;
+
+const LOCK_FILE: &str = "dolos.lock";
+
+#[derive(Debug, Error)]
+pub enum LockError {
+    #[error("storage at {path} is in use by another dolos process ({holder})")]
+    InUse { path: PathBuf, holder: String },
+
+    #[error("io error: {0}")]
+    IO(#[from] std::io::Error),
+}
+
+/// An exclusive lock over the storage path of a dolos instance
+///
+/// The WAL and the ledger live in separate redb files. Each of them is locked
+/// by redb, but two processes could still end up holding one each. This lock
+/// is acquired before opening any of them. The lock is released by the OS when
+/// the process exits, so a crashed process never leaves a stale lock behind.
+/// The pid and start time of the holder are written to the lock file to help
+/// diagnose conflicts.
+#[derive(Debug)]
+pub struct StorageLock(File);
+
+impl StorageLock {
+    pub fn acquire(root: impl AsRef<Path>) -> Result<Self, LockError> {
+        let path = root.as_ref().join(LOCK_FILE);
+
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        match file.try_lock_exclusive() {
+            Ok(()) => (),
+            Err(err) if err.raw_os_error() == fs2::lock_contended_error().raw_os_error() => {
+                // best effort, some platforms don't allow reading a locked file
+                let mut holder = String::new();
+                let _ = file.read_to_string(&mut holder);
+
+                return Err(LockError::InUse {
+                    path: root.as_ref().to_owned(),
+                    holder: holder.trim().to_owned(),
+                });
+            }
+            Err(err) => return Err(err.into()),
+        }
+
+        let started = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "pid {}, started at {}", std::process::id(), started)?;
+        file.flush()?;
+
+        Ok(Self(file))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_concurrent_acquire() {
+        let root = std::env::temp_dir().join(format!("dolos-lock-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+
+        let first = StorageLock::acquire(&root).unwrap();
+
+        let contender = root.clone();
+        let second = std::thread::spawn(move || StorageLock::acquire(contender))
+            .join()
+            .unwrap();
+
+        match second {
+            Err(LockError::InUse { holder, .. }) => {
+                assert!(holder.contains(&format!("pid {}", std::process::id())))
+            }
+            x => panic!("expected lock to be in use, got {x:?}"),
+        }
+
+        // once released, the lock can be acquired again
+        drop(first);
+        StorageLock::acquire(&root).unwrap();
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
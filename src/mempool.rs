@@ -1,5 +1,6 @@
 use crate::{
-    ledger::pparams::Genesis,
+    ledger::{pparams::Genesis, EraCbor, TxoRef, UtxoMap},
+    model::{BlockSlot, SubmitConfig},
     state::LedgerStore,
     uplc::{script_context::SlotConfig, tx, EvalReport},
 };
@@ -19,14 +20,24 @@ use std::{
     borrow::Cow,
     collections::{HashMap, HashSet},
     sync::{Arc, RwLock},
+    time::{Duration, SystemTime},
 };
 use thiserror::Error;
 use tokio::sync::broadcast;
 use tokio_stream::wrappers::BroadcastStream;
-use tracing::debug;
+use tracing::{debug, warn};
 
 type TxHash = Hash<32>;
 
+/// Slots past a tx's TTL before it's evicted, unless configured otherwise
+const DEFAULT_TTL_MARGIN: u64 = 100;
+
+/// Txs queued for a single peer, unless configured otherwise
+const DEFAULT_MAX_PEER_QUEUE: usize = 1_000;
+
+/// Identifies a downstream peer that receives txs from the mempool
+pub type PeerId = String;
+
 #[derive(Debug, Error)]
 pub enum MempoolError {
     #[error("traverse error: {0}")]
@@ -45,6 +56,9 @@ pub enum MempoolError {
     #[error("state error: {0}")]
     StateError(#[from] crate::state::LedgerError),
 
+    #[error("pparams error: {0}")]
+    PParamsError(#[from] crate::ledger::pparams::PParamsError),
+
     #[error("plutus not supported")]
     PlutusNotSupported,
 
@@ -59,6 +73,27 @@ pub struct Tx {
     pub bytes: Vec<u8>,
     // TODO: we'll improve this to track number of confirmations in further iterations.
     pub confirmed: bool,
+    pub received_at: SystemTime,
+    /// The slot after which the tx is no longer valid, if it defines one
+    pub ttl: Option<BlockSlot>,
+}
+
+impl Tx {
+    /// Time elapsed since the tx was received by the mempool
+    pub fn age(&self) -> Duration {
+        self.received_at.elapsed().unwrap_or_default()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DropReason {
+    /// The TTL of the tx is behind the tip of the chain
+    Expired,
+    /// The tx has no TTL and it has been in the mempool for longer than the
+    /// configured retention
+    RetentionElapsed,
+    /// The tx spends outputs of another mempool tx that was dropped
+    ParentDropped,
 }
 
 #[derive(Clone)]
@@ -67,33 +102,204 @@ pub enum TxStage {
     Inflight,
     Acknowledged,
     Confirmed,
+    Dropped(DropReason),
     Unknown,
 }
 
+impl TxStage {
+    /// How far along the tx is on its way to the chain, if it's still alive
+    fn progress(&self) -> Option<u8> {
+        match self {
+            TxStage::Pending => Some(0),
+            TxStage::Inflight => Some(1),
+            TxStage::Acknowledged => Some(2),
+            TxStage::Confirmed => Some(3),
+            TxStage::Dropped(_) | TxStage::Unknown => None,
+        }
+    }
+}
+
+/// Describes how many of the registered peers have acknowledged a tx
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Propagation {
+    pub acknowledged: usize,
+    pub total: usize,
+}
+
 #[derive(Clone)]
 pub struct Event {
     pub new_stage: TxStage,
     pub tx: Tx,
+    pub propagation: Propagation,
 }
 
 #[derive(Default)]
-struct MempoolState {
+struct PeerState {
     pending: Vec<Tx>,
     inflight: Vec<Tx>,
+}
+
+#[derive(Default)]
+struct MempoolState {
+    /// Txs that no peer has acknowledged yet, regardless of the peers
+    /// registered to receive them
+    pending: Vec<Tx>,
+    peers: HashMap<PeerId, PeerState>,
     acknowledged: HashMap<TxHash, Tx>,
+    propagation: HashMap<TxHash, HashSet<PeerId>>,
+    /// The mempool txs whose outputs are spent by each tx
+    parents: HashMap<TxHash, Vec<TxHash>>,
 }
 
-/// A very basic, FIFO, single consumer mempool
+impl MempoolState {
+    fn propagation(&self, tx_hash: &TxHash) -> Propagation {
+        Propagation {
+            acknowledged: self.propagation.get(tx_hash).map(|x| x.len()).unwrap_or(0),
+            total: self.peers.len(),
+        }
+    }
+
+    fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    fn inflight_len(&self) -> usize {
+        self.peers.values().map(|x| x.inflight.len()).sum()
+    }
+
+    /// Iterates the txs known to the mempool, from the most to the least
+    /// advanced stage. Txs inflight for several peers are yielded once per
+    /// peer.
+    fn iter_known(&self) -> impl Iterator<Item = &Tx> {
+        let inflight = self.peers.values().flat_map(|x| x.inflight.iter());
+
+        self.acknowledged
+            .values()
+            .chain(inflight)
+            .chain(self.pending.iter())
+    }
+
+    fn find_known(&self, tx_hash: &TxHash) -> Option<&Tx> {
+        self.iter_known().find(|x| x.hash.eq(tx_hash))
+    }
+
+    fn is_inflight(&self, tx_hash: &TxHash) -> bool {
+        self.peers
+            .values()
+            .any(|x| x.inflight.iter().any(|x| x.hash.eq(tx_hash)))
+    }
+
+    fn stage(&self, tx_hash: &TxHash) -> TxStage {
+        let is_inflight = self.is_inflight(tx_hash);
+
+        let is_pending = self.pending.iter().any(|x| x.hash.eq(tx_hash));
+
+        if let Some(tx) = self.acknowledged.get(tx_hash) {
+            if tx.confirmed {
+                TxStage::Confirmed
+            } else {
+                TxStage::Acknowledged
+            }
+        } else if is_inflight {
+            TxStage::Inflight
+        } else if is_pending {
+            TxStage::Pending
+        } else {
+            TxStage::Unknown
+        }
+    }
+
+    /// Collects the mempool txs a tx depends on, directly or transitively
+    fn ancestors(&self, tx_hash: &TxHash) -> HashSet<TxHash> {
+        let mut out = HashSet::new();
+        let mut queue = vec![*tx_hash];
+
+        while let Some(next) = queue.pop() {
+            for parent in self.parents.get(&next).into_iter().flatten() {
+                if out.insert(*parent) {
+                    queue.push(*parent);
+                }
+            }
+        }
+
+        out
+    }
+
+    /// Lists the txs that can be dropped: pending and unconfirmed
+    /// acknowledged txs. Txs inflight for any peer are skipped until they're
+    /// acknowledged.
+    fn iter_droppable(&self) -> impl Iterator<Item = &Tx> {
+        self.pending
+            .iter()
+            .chain(self.acknowledged.values().filter(|x| !x.confirmed))
+            .filter(|tx| !self.is_inflight(&tx.hash))
+    }
+
+    /// Finds the txs that can't be valid anymore because one of their parents
+    /// is about to be dropped or is no longer known to the mempool
+    fn find_orphans(&self, dropped: &HashMap<TxHash, (Tx, DropReason)>) -> Vec<Tx> {
+        let is_gone = |hash: &TxHash| dropped.contains_key(hash) || self.find_known(hash).is_none();
+
+        self.iter_droppable()
+            .filter(|tx| !dropped.contains_key(&tx.hash))
+            .filter(|tx| {
+                self.parents
+                    .get(&tx.hash)
+                    .is_some_and(|parents| parents.iter().any(is_gone))
+            })
+            .unique_by(|tx| tx.hash)
+            .cloned()
+            .collect()
+    }
+
+    /// Removes txs from the pending set and from the queues of every peer
+    ///
+    /// Inflight txs are never dropped, so there's nothing to remove from the
+    /// inflight lists of the peers.
+    fn forget_pending(&mut self, tx_hashes: &HashSet<TxHash>) {
+        self.pending.retain(|tx| !tx_hashes.contains(&tx.hash));
+
+        for peer in self.peers.values_mut() {
+            peer.pending.retain(|tx| !tx_hashes.contains(&tx.hash));
+        }
+    }
+}
+
+impl PeerState {
+    /// Queues a tx for the peer, skipping the oldest queued ones when the
+    /// queue is full. This keeps unreachable peers from growing their queues
+    /// forever, the skipped txs are still offered to the rest of the peers.
+    fn enqueue(&mut self, peer: &str, tx: Tx, max_queue: usize) {
+        self.pending.push(tx);
+
+        if self.pending.len() > max_queue {
+            let skipped = self.pending.len() - max_queue;
+            self.pending.drain(..skipped);
+
+            warn!(peer, skipped, "peer queue is full, skipping oldest txs");
+        }
+    }
+}
+
+/// A very basic, FIFO mempool
+///
+/// Txs stay pending until a peer acknowledges them, even if there're no peers
+/// registered yet. Pending txs are queued independently for each registered
+/// peer, so that every peer gets offered every tx. Acknowledgements are tracked
+/// per peer to report how far a tx has been propagated.
 #[derive(Clone)]
 pub struct Mempool {
     mempool: Arc<RwLock<MempoolState>>,
     updates: broadcast::Sender<Event>,
     genesis: Arc<Genesis>,
     ledger: LedgerStore,
+    ttl_margin: u64,
+    default_retention: Option<Duration>,
+    max_peer_queue: usize,
 }
 
 impl Mempool {
-    pub fn new(genesis: Arc<Genesis>, ledger: LedgerStore) -> Self {
+    pub fn new(genesis: Arc<Genesis>, ledger: LedgerStore, config: &SubmitConfig) -> Self {
         let mempool = Arc::new(RwLock::new(MempoolState::default()));
         let (updates, _) = broadcast::channel(16);
 
@@ -102,6 +308,9 @@ impl Mempool {
             updates,
             genesis,
             ledger,
+            ttl_margin: config.ttl_margin.unwrap_or(DEFAULT_TTL_MARGIN),
+            default_retention: config.default_retention.map(Duration::from_secs),
+            max_peer_queue: config.max_peer_queue.unwrap_or(DEFAULT_MAX_PEER_QUEUE),
         }
     }
 
@@ -109,26 +318,144 @@ impl Mempool {
         self.updates.subscribe()
     }
 
-    pub fn notify(&self, new_stage: TxStage, tx: Tx) {
-        if self.updates.send(Event { new_stage, tx }).is_err() {
+    pub fn notify(&self, new_stage: TxStage, tx: Tx, propagation: Propagation) {
+        let event = Event {
+            new_stage,
+            tx,
+            propagation,
+        };
+
+        if self.updates.send(event).is_err() {
             debug!("no mempool update receivers");
         }
     }
 
-    fn receive(&self, tx: Tx) {
+    /// Registers a peer that will be offered the txs received by the mempool
+    ///
+    /// The txs that are still pending when the peer is registered are queued
+    /// for it right away.
+    pub fn register_peer(&self, peer: &str) {
         let mut state = self.mempool.write().unwrap();
 
-        state.pending.push(tx.clone());
-        self.notify(TxStage::Pending, tx);
+        if state.peers.contains_key(peer) {
+            return;
+        }
+
+        let mut queue = PeerState::default();
+
+        for tx in state.pending.iter() {
+            queue.enqueue(peer, tx.clone(), self.max_peer_queue);
+        }
+
+        state.peers.insert(peer.to_owned(), queue);
+    }
+
+    /// Returns the inflight txs of a peer back to its pending queue
+    ///
+    /// This is meant to be called when the session with the peer is restarted,
+    /// so that txs that never got acknowledged are offered again.
+    pub fn restore_inflight(&self, peer: &str) {
+        let mut state = self.mempool.write().unwrap();
 
+        if let Some(peer) = state.peers.get_mut(peer) {
+            let mut inflight = std::mem::take(&mut peer.inflight);
+            inflight.append(&mut peer.pending);
+            peer.pending = inflight;
+        }
+    }
+
+    fn log_state(state: &MempoolState) {
         debug!(
-            pending = state.pending.len(),
-            inflight = state.inflight.len(),
+            pending = state.pending_len(),
+            inflight = state.inflight_len(),
             acknowledged = state.acknowledged.len(),
             "mempool state changed"
         );
     }
 
+    fn receive(&self, tx: Tx) {
+        self.receive_chained(tx, vec![]);
+    }
+
+    /// Queues a tx that spends outputs of the given mempool txs
+    ///
+    /// Peers get txs in the order they were received, a dependent tx can only
+    /// be received after its parents, so parents are always offered first.
+    fn receive_chained(&self, tx: Tx, parents: Vec<TxHash>) {
+        let mut state = self.mempool.write().unwrap();
+
+        if !parents.is_empty() {
+            debug!(tx_hash = %tx.hash, ?parents, "tracking chained tx");
+            state.parents.insert(tx.hash, parents);
+        }
+
+        state.pending.push(tx.clone());
+
+        for (id, peer) in state.peers.iter_mut() {
+            peer.enqueue(id, tx.clone(), self.max_peer_queue);
+        }
+
+        let propagation = state.propagation(&tx.hash);
+        self.notify(TxStage::Pending, tx, propagation);
+
+        Self::log_state(&state);
+    }
+
+    /// Resolves the given refs against the outputs of the unconfirmed txs in
+    /// the mempool, looking at the most advanced stages first
+    fn resolve_unconfirmed(&self, refs: &[TxoRef]) -> UtxoMap {
+        let state = self.mempool.read().unwrap();
+
+        let mut out = UtxoMap::new();
+
+        for txoref in refs {
+            let Some(parent) = state.find_known(&txoref.0).filter(|x| !x.confirmed) else {
+                continue;
+            };
+
+            let Ok(parent) = MultiEraTx::decode(&parent.bytes) else {
+                continue;
+            };
+
+            if let Some(output) = parent.produces_at(txoref.1 as usize) {
+                out.insert(txoref.clone(), EraCbor::from(output));
+            }
+        }
+
+        out
+    }
+
+    /// Fetches the utxos consumed by a tx, including those produced by txs
+    /// that are still in the mempool
+    fn resolve_inputs(&self, tx: &MultiEraTx) -> Result<UtxoMap, MempoolError> {
+        let input_refs: Vec<TxoRef> = tx.requires().iter().map(From::from).collect();
+
+        let mut utxos = self.ledger.get_utxos(input_refs.clone())?;
+
+        let missing = input_refs
+            .into_iter()
+            .filter(|x| !utxos.contains_key(x))
+            .collect_vec();
+
+        if !missing.is_empty() {
+            utxos.extend(self.resolve_unconfirmed(&missing));
+        }
+
+        Ok(utxos)
+    }
+
+    /// Lists the unconfirmed mempool txs whose outputs are spent by a tx
+    fn find_parents(&self, tx: &MultiEraTx) -> Vec<TxHash> {
+        let state = self.mempool.read().unwrap();
+
+        tx.requires()
+            .iter()
+            .map(|x| *x.hash())
+            .unique()
+            .filter(|x| state.find_known(x).is_some_and(|x| !x.confirmed))
+            .collect()
+    }
+
     pub fn validate(&self, tx: &MultiEraTx) -> Result<(), MempoolError> {
         let tip = self.ledger.cursor()?;
 
@@ -138,7 +465,7 @@ impl Mempool {
 
         let updates: Vec<_> = updates.into_iter().map(TryInto::try_into).try_collect()?;
 
-        let eras = crate::ledger::pparams::fold(&self.genesis, &updates);
+        let eras = crate::ledger::pparams::fold(&self.genesis, &updates)?;
 
         let era = eras.era_for_slot(tip.as_ref().unwrap().0);
 
@@ -154,9 +481,7 @@ impl Mempool {
             acnt: Some(AccountState::default()),
         };
 
-        let input_refs = tx.requires().iter().map(From::from).collect();
-
-        let utxos = self.ledger.get_utxos(input_refs)?;
+        let utxos = self.resolve_inputs(tx)?;
 
         let mut pallas_utxos = UTxOs::new();
 
@@ -187,7 +512,7 @@ impl Mempool {
 
         let updates: Vec<_> = updates.into_iter().map(TryInto::try_into).try_collect()?;
 
-        let eras = crate::ledger::pparams::fold(&self.genesis, &updates);
+        let eras = crate::ledger::pparams::fold(&self.genesis, &updates)?;
 
         let slot_config = SlotConfig {
             slot_length: eras.edge().pparams.slot_length(),
@@ -195,9 +520,7 @@ impl Mempool {
             zero_time: eras.edge().start.timestamp.timestamp().try_into().unwrap(),
         };
 
-        let input_refs = tx.requires().iter().map(From::from).collect();
-
-        let utxos = self.ledger.get_utxos(input_refs)?;
+        let utxos = self.resolve_inputs(tx)?;
 
         let report = tx::eval_tx(tx, &eras.edge().pparams, &utxos, &slot_config)?;
 
@@ -226,6 +549,7 @@ impl Mempool {
         }
 
         let hash = tx.hash();
+        let parents = self.find_parents(&tx);
 
         let tx = Tx {
             hash,
@@ -233,120 +557,270 @@ impl Mempool {
             era: u16::from(tx.era()) - 1,
             bytes: cbor.into(),
             confirmed: false,
+            received_at: SystemTime::now(),
+            ttl: tx.ttl(),
         };
 
-        self.receive(tx);
+        self.receive_chained(tx, parents);
 
         Ok(hash)
     }
 
-    pub fn request(&self, desired: usize) -> Vec<Tx> {
-        let available = self.pending_total();
-        self.request_exact(std::cmp::min(desired, available))
+    pub fn request(&self, peer: &str, desired: usize) -> Vec<Tx> {
+        let available = self.pending_total(peer);
+        self.request_exact(peer, std::cmp::min(desired, available))
     }
 
-    pub fn request_exact(&self, count: usize) -> Vec<Tx> {
+    pub fn request_exact(&self, peer: &str, count: usize) -> Vec<Tx> {
         let mut state = self.mempool.write().unwrap();
 
-        let selected = state.pending.drain(..count).collect_vec();
+        let Some(queue) = state.peers.get_mut(peer) else {
+            return vec![];
+        };
+
+        let selected = queue.pending.drain(..count).collect_vec();
+        queue.inflight.extend(selected.iter().cloned());
 
         for tx in selected.iter() {
-            state.inflight.push(tx.clone());
-            self.notify(TxStage::Inflight, tx.clone());
+            let propagation = state.propagation(&tx.hash);
+            self.notify(TxStage::Inflight, tx.clone(), propagation);
         }
 
-        debug!(
-            pending = state.pending.len(),
-            inflight = state.inflight.len(),
-            acknowledged = state.acknowledged.len(),
-            "mempool state changed"
-        );
+        Self::log_state(&state);
 
         selected
     }
 
-    pub fn acknowledge(&self, count: usize) {
-        debug!(n = count, "acknowledging txs");
+    pub fn acknowledge(&self, peer: &str, count: usize) {
+        debug!(n = count, peer, "acknowledging txs");
 
         let mut state = self.mempool.write().unwrap();
 
-        let selected = state.inflight.drain(..count).collect_vec();
+        let Some(queue) = state.peers.get_mut(peer) else {
+            return;
+        };
+
+        let count = std::cmp::min(count, queue.inflight.len());
+        let selected = queue.inflight.drain(..count).collect_vec();
 
         for tx in selected {
-            state.acknowledged.insert(tx.hash, tx.clone());
-            self.notify(TxStage::Acknowledged, tx.clone());
+            state
+                .propagation
+                .entry(tx.hash)
+                .or_default()
+                .insert(peer.to_owned());
+
+            state.pending.retain(|x| x.hash != tx.hash);
+
+            let tx = state.acknowledged.entry(tx.hash).or_insert(tx).clone();
+
+            let propagation = state.propagation(&tx.hash);
+            self.notify(TxStage::Acknowledged, tx, propagation);
         }
 
-        debug!(
-            pending = state.pending.len(),
-            inflight = state.inflight.len(),
-            acknowledged = state.acknowledged.len(),
-            "mempool state changed"
-        );
+        Self::log_state(&state);
     }
 
-    pub fn find_inflight(&self, tx_hash: &TxHash) -> Option<Tx> {
+    pub fn find_inflight(&self, peer: &str, tx_hash: &TxHash) -> Option<Tx> {
         let state = self.mempool.read().unwrap();
-        state.inflight.iter().find(|x| x.hash.eq(tx_hash)).cloned()
+
+        state
+            .peers
+            .get(peer)
+            .and_then(|x| x.inflight.iter().find(|x| x.hash.eq(tx_hash)))
+            .cloned()
     }
 
     pub fn find_pending(&self, tx_hash: &TxHash) -> Option<Tx> {
         let state = self.mempool.read().unwrap();
+
         state.pending.iter().find(|x| x.hash.eq(tx_hash)).cloned()
     }
 
-    pub fn pending_total(&self) -> usize {
+    pub fn pending_total(&self, peer: &str) -> usize {
         let state = self.mempool.read().unwrap();
-        state.pending.len()
+        state.peers.get(peer).map(|x| x.pending.len()).unwrap_or(0)
     }
 
+    /// Reports the stage of a tx
+    ///
+    /// A tx that spends outputs of other mempool txs can't be further along
+    /// than any of them, so the least advanced stage of its ancestors is
+    /// reported instead when it's behind the stage of the tx itself.
     pub fn check_stage(&self, tx_hash: &TxHash) -> TxStage {
         let state = self.mempool.read().unwrap();
 
-        if let Some(tx) = state.acknowledged.get(tx_hash) {
-            if tx.confirmed {
-                TxStage::Confirmed
-            } else {
-                TxStage::Acknowledged
+        let stage = state.stage(tx_hash);
+
+        let Some(mut progress) = stage.progress() else {
+            return stage;
+        };
+
+        let mut weakest = stage;
+
+        for ancestor in state.ancestors(tx_hash) {
+            let stage = state.stage(&ancestor);
+
+            if let Some(x) = stage.progress().filter(|x| *x < progress) {
+                progress = x;
+                weakest = stage;
             }
-        } else if self.find_inflight(tx_hash).is_some() {
-            TxStage::Inflight
-        } else if self.find_pending(tx_hash).is_some() {
-            TxStage::Pending
-        } else {
-            TxStage::Unknown
         }
+
+        weakest
     }
 
-    pub fn apply_block(&self, block: &MultiEraBlock) {
+    /// Reports how many of the registered peers have acknowledged a tx
+    pub fn check_propagation(&self, tx_hash: &TxHash) -> Propagation {
+        let state = self.mempool.read().unwrap();
+        state.propagation(tx_hash)
+    }
+
+    fn drop_reason(&self, tx: &Tx, tip: BlockSlot) -> Option<DropReason> {
+        match tx.ttl {
+            Some(ttl) if ttl.saturating_add(self.ttl_margin) < tip => Some(DropReason::Expired),
+            Some(_) => None,
+            None => self
+                .default_retention
+                .filter(|retention| tx.age() >= *retention)
+                .map(|_| DropReason::RetentionElapsed),
+        }
+    }
+
+    /// Drops the txs that can't make it on-chain anymore
+    ///
+    /// Txs are dropped once their TTL is more than `ttl_margin` slots behind
+    /// the tip, or once the default retention elapses for those without a TTL.
+    /// Inflight txs are left alone until the peer acknowledges them, otherwise
+    /// we'd break the ordering of the acknowledgements. Txs that spend outputs
+    /// of a dropped tx are dropped along with it. Dropped txs are notified
+    /// from oldest to newest.
+    pub fn evict(&self, tip: BlockSlot) {
         let mut state = self.mempool.write().unwrap();
 
-        if state.acknowledged.is_empty() {
+        let mut dropped: HashMap<TxHash, (Tx, DropReason)> = HashMap::new();
+
+        for tx in state.iter_droppable() {
+            if let Some(reason) = self.drop_reason(tx, tip) {
+                dropped.insert(tx.hash, (tx.clone(), reason));
+            }
+        }
+
+        loop {
+            let orphans = state.find_orphans(&dropped);
+
+            if orphans.is_empty() {
+                break;
+            }
+
+            for tx in orphans {
+                dropped.insert(tx.hash, (tx, DropReason::ParentDropped));
+            }
+        }
+
+        if dropped.is_empty() {
             return;
         }
 
-        for tx in block.txs() {
-            let tx_hash = tx.hash();
+        state.forget_pending(&dropped.keys().copied().collect());
+
+        state
+            .acknowledged
+            .retain(|hash, _| !dropped.contains_key(hash));
+
+        let dropped = dropped
+            .into_values()
+            .sorted_by_key(|(tx, _)| (tx.received_at, tx.hash));
+
+        for (tx, reason) in dropped {
+            debug!(tx_hash = %tx.hash, ?reason, "dropping tx");
+
+            let propagation = state.propagation(&tx.hash);
+            state.propagation.remove(&tx.hash);
+            state.parents.remove(&tx.hash);
+
+            self.notify(TxStage::Dropped(reason), tx, propagation);
+        }
+
+        Self::log_state(&state);
+    }
+
+    pub fn apply_block(&self, block: &MultiEraBlock) {
+        self.evict(block.slot());
+
+        let tx_hashes = block.txs().iter().map(|x| x.hash()).collect_vec();
+        self.confirm(&tx_hashes);
+    }
+
+    pub fn undo_block(&self, block: &MultiEraBlock) {
+        let tx_hashes = block.txs().iter().map(|x| x.hash()).collect_vec();
+        self.unconfirm(&tx_hashes);
+    }
+
+    /// Marks txs as confirmed
+    ///
+    /// Confirmed txs are no longer offered to any peer, even to those that
+    /// never acknowledged them. Pending txs can also be confirmed if they
+    /// reached the chain through some other path.
+    fn confirm(&self, tx_hashes: &[TxHash]) {
+        let mut state = self.mempool.write().unwrap();
+
+        let confirmed: HashSet<_> = tx_hashes.iter().copied().collect();
+
+        let pending = state
+            .pending
+            .iter()
+            .filter(|x| confirmed.contains(&x.hash))
+            .cloned()
+            .collect_vec();
+
+        state.forget_pending(&confirmed);
+
+        for tx in pending {
+            state.acknowledged.entry(tx.hash).or_insert(tx);
+        }
 
-            if let Some(acknowledged_tx) = state.acknowledged.get_mut(&tx_hash) {
+        for tx_hash in tx_hashes {
+            if let Some(acknowledged_tx) = state.acknowledged.get_mut(tx_hash) {
                 acknowledged_tx.confirmed = true;
-                self.notify(TxStage::Confirmed, acknowledged_tx.clone());
+                let acknowledged_tx = acknowledged_tx.clone();
+
+                let propagation = state.propagation(tx_hash);
+                self.notify(TxStage::Confirmed, acknowledged_tx, propagation);
                 debug!(%tx_hash, "confirming tx");
             }
         }
     }
 
-    pub fn undo_block(&self, block: &MultiEraBlock) {
+    /// Reverts the confirmation of txs that were rolled back
+    ///
+    /// Rolled back txs go back to being acknowledged, so they can still expire
+    /// and take their dependents with them. Those that no peer acknowledged go
+    /// back to pending and are offered to the peers again.
+    fn unconfirm(&self, tx_hashes: &[TxHash]) {
         let mut state = self.mempool.write().unwrap();
 
         if state.acknowledged.is_empty() {
             return;
         }
 
-        for tx in block.txs() {
-            let tx_hash = tx.hash();
+        for tx_hash in tx_hashes {
+            let acknowledged_by = state.propagation.get(tx_hash).map(|x| x.len());
+
+            if acknowledged_by.unwrap_or(0) == 0 {
+                let Some(mut tx) = state.acknowledged.remove(tx_hash) else {
+                    continue;
+                };
+
+                debug!(%tx_hash, "returning tx to pending");
+
+                tx.confirmed = false;
+                state.pending.push(tx.clone());
 
-            if let Some(acknowledged_tx) = state.acknowledged.get_mut(&tx_hash) {
+                for (id, peer) in state.peers.iter_mut() {
+                    peer.enqueue(id, tx.clone(), self.max_peer_queue);
+                }
+            } else if let Some(acknowledged_tx) = state.acknowledged.get_mut(tx_hash) {
                 acknowledged_tx.confirmed = false;
                 debug!(%tx_hash, "un-confirming tx");
             }
@@ -393,3 +867,408 @@ impl futures_core::Stream for UpdateFilter {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_json<T>(path: &str) -> T
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let file = std::fs::File::open(path).unwrap();
+        serde_json::from_reader(file).unwrap()
+    }
+
+    fn test_mempool_with(config: SubmitConfig) -> Mempool {
+        let test_data = "src/ledger/pparams/test_data/mainnet/genesis";
+
+        let genesis = Genesis {
+            byron: load_json(&format!("{test_data}/byron_genesis.json")),
+            shelley: load_json(&format!("{test_data}/shelley_genesis.json")),
+            alonzo: load_json(&format!("{test_data}/alonzo_genesis.json")),
+            conway: load_json(&format!("{test_data}/conway_genesis.json")),
+            force_protocol: None,
+            cost_model_tolerance: Default::default(),
+        };
+
+        let ledger = crate::state::redb::LedgerStore::in_memory_v2().unwrap();
+
+        Mempool::new(Arc::new(genesis), ledger.into(), &config)
+    }
+
+    fn test_mempool() -> Mempool {
+        test_mempool_with(SubmitConfig::default())
+    }
+
+    fn dummy_tx(seed: u8) -> Tx {
+        Tx {
+            hash: Hash::new([seed; 32]),
+            era: 0,
+            bytes: vec![seed],
+            confirmed: false,
+            received_at: SystemTime::UNIX_EPOCH + Duration::from_secs(seed.into()),
+            ttl: None,
+        }
+    }
+
+    fn dummy_tx_with_ttl(seed: u8, ttl: BlockSlot) -> Tx {
+        Tx {
+            ttl: Some(ttl),
+            ..dummy_tx(seed)
+        }
+    }
+
+    fn drain_dropped(events: &mut broadcast::Receiver<Event>) -> Vec<(TxHash, DropReason)> {
+        let mut out = vec![];
+
+        while let Ok(event) = events.try_recv() {
+            if let TxStage::Dropped(reason) = event.new_stage {
+                out.push((event.tx.hash, reason));
+            }
+        }
+
+        out
+    }
+
+    #[test]
+    fn test_propagation_to_multiple_peers() {
+        let mempool = test_mempool();
+
+        mempool.register_peer("a");
+        mempool.register_peer("b");
+
+        let tx = dummy_tx(1);
+        mempool.receive(tx.clone());
+
+        // every peer gets offered the tx
+        assert_eq!(mempool.pending_total("a"), 1);
+        assert_eq!(mempool.pending_total("b"), 1);
+        assert!(matches!(mempool.check_stage(&tx.hash), TxStage::Pending));
+
+        assert_eq!(mempool.request("a", 10), vec![tx.clone()]);
+        assert!(matches!(mempool.check_stage(&tx.hash), TxStage::Inflight));
+        assert!(mempool.find_inflight("a", &tx.hash).is_some());
+        assert!(mempool.find_inflight("b", &tx.hash).is_none());
+
+        mempool.acknowledge("a", 1);
+        assert!(matches!(
+            mempool.check_stage(&tx.hash),
+            TxStage::Acknowledged
+        ));
+
+        assert_eq!(
+            mempool.check_propagation(&tx.hash),
+            Propagation {
+                acknowledged: 1,
+                total: 2
+            }
+        );
+
+        assert_eq!(mempool.request("b", 10), vec![tx.clone()]);
+        mempool.acknowledge("b", 1);
+
+        assert_eq!(
+            mempool.check_propagation(&tx.hash),
+            Propagation {
+                acknowledged: 2,
+                total: 2
+            }
+        );
+    }
+
+    #[test]
+    fn test_restore_inflight() {
+        let mempool = test_mempool();
+
+        mempool.register_peer("a");
+        mempool.register_peer("b");
+
+        mempool.receive(dummy_tx(1));
+        mempool.receive(dummy_tx(2));
+
+        assert_eq!(mempool.request("a", 1).len(), 1);
+        assert_eq!(mempool.pending_total("a"), 1);
+
+        // a failing peer doesn't affect the queue of the other ones
+        mempool.restore_inflight("a");
+        assert_eq!(mempool.pending_total("a"), 2);
+        assert_eq!(mempool.pending_total("b"), 2);
+
+        // restored txs are offered first
+        assert_eq!(mempool.request("a", 1), vec![dummy_tx(1)]);
+    }
+
+    #[test]
+    fn test_pending_without_peers() {
+        let mempool = test_mempool();
+
+        let tx = dummy_tx(1);
+        mempool.receive(tx.clone());
+
+        // txs are kept even if there's no peer to offer them to yet
+        assert!(matches!(mempool.check_stage(&tx.hash), TxStage::Pending));
+        assert_eq!(mempool.find_pending(&tx.hash), Some(tx.clone()));
+
+        // late peers get the txs that are still pending
+        mempool.register_peer("a");
+        assert_eq!(mempool.request("a", 10), vec![tx.clone()]);
+        mempool.acknowledge("a", 1);
+
+        mempool.register_peer("b");
+        assert_eq!(mempool.pending_total("b"), 0);
+    }
+
+    #[test]
+    fn test_confirm_clears_every_peer() {
+        let mempool = test_mempool();
+
+        mempool.register_peer("a");
+        mempool.register_peer("b");
+
+        let (a, b) = (dummy_tx(1), dummy_tx(2));
+        mempool.receive(a.clone());
+        mempool.receive(b.clone());
+
+        assert_eq!(mempool.request("a", 1), vec![a.clone()]);
+        mempool.acknowledge("a", 1);
+
+        // peer b never got the tx, but it's on-chain already
+        mempool.confirm(&[a.hash, b.hash]);
+
+        assert_eq!(mempool.pending_total("a"), 0);
+        assert_eq!(mempool.pending_total("b"), 0);
+        assert!(matches!(mempool.check_stage(&a.hash), TxStage::Confirmed));
+        assert!(matches!(mempool.check_stage(&b.hash), TxStage::Confirmed));
+
+        // a rolled back tx that no peer acknowledged is offered again
+        mempool.unconfirm(&[a.hash, b.hash]);
+
+        assert!(matches!(
+            mempool.check_stage(&a.hash),
+            TxStage::Acknowledged
+        ));
+        assert!(matches!(mempool.check_stage(&b.hash), TxStage::Pending));
+        assert_eq!(mempool.request("b", 10), vec![b.clone()]);
+    }
+
+    #[test]
+    fn test_max_peer_queue() {
+        let mempool = test_mempool_with(SubmitConfig {
+            max_peer_queue: Some(2),
+            ..Default::default()
+        });
+
+        mempool.register_peer("a");
+
+        for seed in 1..=4 {
+            mempool.receive(dummy_tx(seed));
+        }
+
+        // the queue of a peer that never asks for txs doesn't grow forever
+        assert_eq!(mempool.pending_total("a"), 2);
+
+        // skipped txs are still pending for the rest of the peers
+        assert!(matches!(
+            mempool.check_stage(&dummy_tx(1).hash),
+            TxStage::Pending
+        ));
+
+        mempool.register_peer("b");
+        assert_eq!(mempool.request("b", 10), vec![dummy_tx(3), dummy_tx(4)]);
+    }
+
+    #[test]
+    fn test_evict_expired_in_order() {
+        let mempool = test_mempool_with(SubmitConfig {
+            ttl_margin: Some(0),
+            ..Default::default()
+        });
+
+        mempool.register_peer("a");
+        mempool.register_peer("b");
+
+        let mut events = mempool.subscribe();
+
+        mempool.receive(dummy_tx_with_ttl(3, 10));
+        mempool.receive(dummy_tx_with_ttl(2, 10));
+        mempool.receive(dummy_tx_with_ttl(1, 20));
+        mempool.receive(dummy_tx(4));
+
+        // nothing is expired yet
+        mempool.evict(10);
+        assert!(drain_dropped(&mut events).is_empty());
+
+        // a tx acknowledged but not yet confirmed can also expire
+        assert_eq!(mempool.request("a", 1), vec![dummy_tx_with_ttl(3, 10)]);
+        mempool.acknowledge("a", 1);
+
+        mempool.evict(11);
+
+        // dropped txs are notified oldest first and only once, even if they were
+        // queued for several peers
+        assert_eq!(
+            drain_dropped(&mut events),
+            vec![
+                (dummy_tx(2).hash, DropReason::Expired),
+                (dummy_tx(3).hash, DropReason::Expired),
+            ]
+        );
+
+        assert!(matches!(
+            mempool.check_stage(&dummy_tx(3).hash),
+            TxStage::Unknown
+        ));
+        assert_eq!(mempool.check_propagation(&dummy_tx(3).hash).acknowledged, 0);
+        assert_eq!(mempool.pending_total("a"), 2);
+        assert_eq!(mempool.pending_total("b"), 2);
+
+        mempool.evict(21);
+
+        assert_eq!(
+            drain_dropped(&mut events),
+            vec![(dummy_tx(1).hash, DropReason::Expired)]
+        );
+
+        // without a default retention, txs with no TTL are kept around
+        assert_eq!(mempool.request("b", 10), vec![dummy_tx(4)]);
+    }
+
+    #[test]
+    fn test_evict_ttl_margin() {
+        let mempool = test_mempool_with(SubmitConfig {
+            ttl_margin: Some(5),
+            ..Default::default()
+        });
+
+        mempool.register_peer("a");
+
+        let mut events = mempool.subscribe();
+
+        mempool.receive(dummy_tx_with_ttl(1, 10));
+
+        mempool.evict(15);
+        assert!(drain_dropped(&mut events).is_empty());
+
+        mempool.evict(16);
+        assert_eq!(
+            drain_dropped(&mut events),
+            vec![(dummy_tx(1).hash, DropReason::Expired)]
+        );
+    }
+
+    #[test]
+    fn test_evict_default_retention() {
+        let mempool = test_mempool_with(SubmitConfig {
+            default_retention: Some(60),
+            ..Default::default()
+        });
+
+        mempool.register_peer("a");
+
+        let mut events = mempool.subscribe();
+
+        let recent = Tx {
+            received_at: SystemTime::now(),
+            ..dummy_tx(2)
+        };
+
+        mempool.receive(dummy_tx(1));
+        mempool.receive(recent.clone());
+        mempool.receive(dummy_tx_with_ttl(3, 100));
+
+        mempool.evict(10);
+
+        // only the tx without a TTL received long ago is dropped
+        assert_eq!(
+            drain_dropped(&mut events),
+            vec![(dummy_tx(1).hash, DropReason::RetentionElapsed)]
+        );
+
+        assert_eq!(mempool.pending_total("a"), 2);
+    }
+
+    #[test]
+    fn test_chained_stage() {
+        let mempool = test_mempool();
+
+        mempool.register_peer("a");
+
+        let (a, b, c) = (dummy_tx(1), dummy_tx(2), dummy_tx(3));
+
+        mempool.receive(a.clone());
+        mempool.receive_chained(b.clone(), vec![a.hash]);
+        mempool.receive_chained(c.clone(), vec![b.hash]);
+
+        // parents are offered before their children
+        assert_eq!(
+            mempool.request("a", 10),
+            vec![a.clone(), b.clone(), c.clone()]
+        );
+        mempool.acknowledge("a", 3);
+
+        mempool.confirm(&[a.hash, b.hash, c.hash]);
+        assert!(matches!(mempool.check_stage(&c.hash), TxStage::Confirmed));
+
+        // once the parent is rolled back, the dependents can't be considered
+        // confirmed anymore
+        mempool.unconfirm(&[a.hash]);
+
+        assert!(matches!(
+            mempool.check_stage(&b.hash),
+            TxStage::Acknowledged
+        ));
+        assert!(matches!(
+            mempool.check_stage(&c.hash),
+            TxStage::Acknowledged
+        ));
+    }
+
+    #[test]
+    fn test_evict_dependents() {
+        let mempool = test_mempool_with(SubmitConfig {
+            ttl_margin: Some(0),
+            ..Default::default()
+        });
+
+        mempool.register_peer("a");
+
+        let mut events = mempool.subscribe();
+
+        let (a, b, c) = (dummy_tx_with_ttl(1, 10), dummy_tx(2), dummy_tx(3));
+
+        mempool.receive(a.clone());
+        mempool.receive_chained(b.clone(), vec![a.hash]);
+        mempool.receive_chained(c.clone(), vec![b.hash]);
+
+        assert_eq!(mempool.request("a", 2), vec![a.clone(), b.clone()]);
+        mempool.acknowledge("a", 1);
+
+        // the parent was confirmed and then rolled back, so it can still expire
+        mempool.confirm(&[a.hash]);
+        mempool.unconfirm(&[a.hash]);
+
+        mempool.evict(11);
+
+        // the inflight child stays until acknowledged, and so does its own child
+        assert_eq!(
+            drain_dropped(&mut events),
+            vec![(a.hash, DropReason::Expired)]
+        );
+        assert!(matches!(mempool.check_stage(&b.hash), TxStage::Inflight));
+        assert!(matches!(mempool.check_stage(&c.hash), TxStage::Pending));
+
+        mempool.acknowledge("a", 1);
+        mempool.evict(12);
+
+        assert_eq!(
+            drain_dropped(&mut events),
+            vec![
+                (b.hash, DropReason::ParentDropped),
+                (c.hash, DropReason::ParentDropped),
+            ]
+        );
+        assert!(matches!(mempool.check_stage(&c.hash), TxStage::Unknown));
+        assert_eq!(mempool.pending_total("a"), 0);
+    }
+}
@@ -36,9 +36,153 @@ pub struct UpstreamConfig {
 
     #[serde(default)]
     pub is_testnet: bool,
+
+    /// Peers to fail over to when the main peer can't be reached
+    #[serde(default)]
+    pub fallback_peers: Vec<String>,
+
+    /// Seconds to wait for news from the peer while at the tip before failing
+    /// over to the next one
+    pub peer_timeout: Option<u64>,
 }
 
 #[derive(Serialize, Deserialize, Default)]
 pub struct SubmitConfig {
     pub prune_height: Option<u64>,
+
+    /// Additional peers to propagate submitted txs to, besides the upstream
+    #[serde(default)]
+    pub propagation_peers: Vec<String>,
+
+    /// Slots past the TTL of a tx before it's dropped from the mempool
+    pub ttl_margin: Option<u64>,
+
+    /// Seconds to keep txs without a TTL in the mempool, forever if not set
+    pub default_retention: Option<u64>,
+
+    /// Max txs queued for a single peer before the oldest ones are skipped
+    pub max_peer_queue: Option<usize>,
+}
+
+/// How much durability to trade for write throughput
+///
+/// | mode       | wal       | ledger    |
+/// | ---------- | --------- | --------- |
+/// | `strict`   | immediate | immediate |
+/// | `balanced` | immediate | eventual  |
+/// | `fast`     | eventual  | eventual  |
+///
+/// In `strict` and `balanced` modes the WAL is flushed on every commit, so
+/// after a crash the ledger might be behind the WAL (recovered by replaying it)
+/// but never ahead of it. In `fast` mode both are flushed independently and
+/// the ledger might end up ahead of the WAL, which needs a ledger rebuild.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DurabilityMode {
+    Strict,
+    #[default]
+    Balanced,
+    Fast,
+}
+
+impl DurabilityMode {
+    /// Durability of the commits to the WAL
+    pub fn wal(&self) -> redb::Durability {
+        match self {
+            DurabilityMode::Strict => redb::Durability::Immediate,
+            DurabilityMode::Balanced => redb::Durability::Immediate,
+            DurabilityMode::Fast => redb::Durability::Eventual,
+        }
+    }
+
+    /// Durability of the commits to the ledger
+    pub fn ledger(&self) -> redb::Durability {
+        match self {
+            DurabilityMode::Strict => redb::Durability::Immediate,
+            DurabilityMode::Balanced => redb::Durability::Eventual,
+            DurabilityMode::Fast => redb::Durability::Eventual,
+        }
+    }
+}
+
+/// Key count and size of a single table within a store
+#[derive(Debug, Clone, Serialize)]
+pub struct TableStats {
+    pub name: String,
+    pub keys: u64,
+    pub stored_bytes: u64,
+    pub metadata_bytes: u64,
+    pub fragmented_bytes: u64,
+}
+
+impl TableStats {
+    /// Gathers the stats of every table (regular or multimap) of a redb database
+    pub fn collect_redb(db: &redb::Database) -> Result<Vec<Self>, redb::Error> {
+        use redb::{MultimapTableHandle as _, ReadableTableMetadata as _, TableHandle as _};
+
+        let rx = db.begin_read()?;
+
+        let mut out = vec![];
+
+        for handle in rx.list_tables()? {
+            let name = handle.name().to_owned();
+            let table = rx.open_untyped_table(handle)?;
+            let stats = table.stats()?;
+
+            out.push(Self {
+                name,
+                keys: table.len()?,
+                stored_bytes: stats.stored_bytes(),
+                metadata_bytes: stats.metadata_bytes(),
+                fragmented_bytes: stats.fragmented_bytes(),
+            });
+        }
+
+        for handle in rx.list_multimap_tables()? {
+            let name = handle.name().to_owned();
+            let table = rx.open_untyped_multimap_table(handle)?;
+            let stats = table.stats()?;
+
+            out.push(Self {
+                name,
+                keys: table.len()?,
+                stored_bytes: stats.stored_bytes(),
+                metadata_bytes: stats.metadata_bytes(),
+                fragmented_bytes: stats.fragmented_bytes(),
+            });
+        }
+
+        out.sort_by(|a, b| a.name.cmp(&b.name));
+
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ledger_never_ahead_of_immediate_wal() {
+        for mode in [DurabilityMode::Strict, DurabilityMode::Balanced] {
+            assert_eq!(mode.wal(), redb::Durability::Immediate, "{mode:?}");
+        }
+
+        // fast mode flushes neither, so there are no guarantees between the two
+        assert_eq!(DurabilityMode::Fast.wal(), redb::Durability::Eventual);
+        assert_eq!(DurabilityMode::Fast.ledger(), redb::Durability::Eventual);
+    }
+
+    #[test]
+    fn test_durability_mode_from_config() {
+        let mode: DurabilityMode = serde_json::from_str("\"strict\"").unwrap();
+        assert_eq!(mode, DurabilityMode::Strict);
+
+        // balanced matches the behavior before the setting existed
+        assert_eq!(DurabilityMode::default().wal(), redb::Durability::Immediate);
+        assert_eq!(
+            DurabilityMode::default().ledger(),
+            redb::Durability::Eventual
+        );
+    }
 }
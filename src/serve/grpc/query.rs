@@ -237,7 +237,8 @@ impl u5c::query::query_service_server::QueryService for QueryServiceImpl {
             .try_collect::<_, _, pallas::codec::minicbor::decode::Error>()
             .map_err(|e| Status::internal(e.to_string()))?;
 
-        let summary = pparams::fold(&self.genesis, &updates);
+        let summary =
+            pparams::fold(&self.genesis, &updates).map_err(|e| Status::internal(e.to_string()))?;
 
         let era = summary.era_for_slot(tip.as_ref().unwrap().0);
 
@@ -39,12 +39,27 @@ fn tx_stage_to_u5c(stage: crate::mempool::TxStage) -> i32 {
     }
 }
 
+/// Maps the stage of an event taking into account how far the tx has been
+/// propagated. A tx acknowledged by only some of the peers is still making its
+/// way through the network, so it's reported as such until every peer
+/// acknowledges it.
+fn event_stage_to_u5c(event: &Event) -> i32 {
+    match event.new_stage {
+        crate::mempool::TxStage::Acknowledged
+            if event.propagation.acknowledged < event.propagation.total =>
+        {
+            Stage::Network as i32
+        }
+        _ => tx_stage_to_u5c(event.new_stage.clone()),
+    }
+}
+
 fn event_to_watch_mempool_response(event: Event) -> WatchMempoolResponse {
     WatchMempoolResponse {
         tx: TxInMempool {
             r#ref: event.tx.hash.to_vec().into(),
             native_bytes: event.tx.bytes.to_vec().into(),
-            stage: tx_stage_to_u5c(event.new_stage),
+            stage: event_stage_to_u5c(&event),
             parsed_state: None, // TODO
         }
         .into(),
@@ -53,7 +68,7 @@ fn event_to_watch_mempool_response(event: Event) -> WatchMempoolResponse {
 
 fn event_to_wait_for_tx_response(event: Event) -> WaitForTxResponse {
     WaitForTxResponse {
-        stage: tx_stage_to_u5c(event.new_stage),
+        stage: event_stage_to_u5c(&event),
         r#ref: event.tx.hash.to_vec().into(),
     }
 }
@@ -1,4 +1,5 @@
 use crate::{
+    ledger::address::{classify_address, AddressKind},
     state::LedgerStore,
     wal::{self, ChainPoint, WalReader as _},
 };
@@ -23,18 +24,19 @@ fn outputs_match_address(
     let delegation_matches = pattern.delegation_part.is_empty()
         || outputs.iter().any(|o| {
             let addr = Address::from_bytes(&o.address).unwrap();
-            match addr {
-                Address::Shelley(s) => s.delegation().to_vec().eq(&pattern.delegation_part),
+            match classify_address(&addr) {
+                AddressKind::Shelley { stake, .. } => stake
+                    .as_deref()
+                    .is_some_and(|x| x.eq(&pattern.delegation_part)),
                 _ => false,
             }
         });
     let payment_matches = pattern.payment_part.is_empty()
         || outputs.iter().any(|o| {
             let addr = Address::from_bytes(&o.address).unwrap();
-            match addr {
-                Address::Shelley(s) => s.payment().to_vec().eq(&pattern.payment_part),
-                _ => false,
-            }
+            classify_address(&addr)
+                .payment()
+                .is_some_and(|x| x.eq(&pattern.payment_part))
         });
 
     exact_matches && delegation_matches && payment_matches
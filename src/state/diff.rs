@@ -0,0 +1,405 @@
+use itertools::{EitherOrBoth, Itertools as _};
+use serde::Serialize;
+use serde_json::json;
+use std::collections::HashSet;
+
+use super::{redb::LedgerStore, LedgerError};
+use crate::ledger::{EraCbor, TxoRef};
+
+/// A group of ledger entries that can be compared independently
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Namespace {
+    Cursor,
+    PParams,
+    Utxos,
+}
+
+impl Namespace {
+    pub const ALL: [Namespace; 3] = [Namespace::Cursor, Namespace::PParams, Namespace::Utxos];
+}
+
+impl std::fmt::Display for Namespace {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Namespace::Cursor => write!(f, "cursor"),
+            Namespace::PParams => write!(f, "pparams"),
+            Namespace::Utxos => write!(f, "utxos"),
+        }
+    }
+}
+
+impl std::str::FromStr for Namespace {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Namespace::ALL
+            .into_iter()
+            .find(|x| x.to_string().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown namespace '{s}', expected cursor, pparams or utxos"))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DiffOptions {
+    /// Max number of differences to keep in the details of each namespace
+    pub max_details: usize,
+    /// Compare this many random utxos from each side instead of the whole set
+    pub sample: Option<usize>,
+    /// Seed for the random choice of the sampled utxos
+    pub seed: u64,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        Self {
+            max_details: 10,
+            sample: None,
+            seed: 0,
+        }
+    }
+}
+
+/// An entry that is different on each side, or only present on one of them
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Difference {
+    pub key: String,
+    pub local: Option<serde_json::Value>,
+    pub other: Option<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NamespaceDiff {
+    pub namespace: Namespace,
+    pub matching: u64,
+    pub mismatched: u64,
+    pub only_local: u64,
+    pub only_other: u64,
+    /// Total number of entries on each side, when it's known
+    pub local_count: Option<u64>,
+    pub other_count: Option<u64>,
+    /// Whether only a sample of the entries was compared
+    pub sampled: bool,
+    /// The first differences found, up to the configured max
+    pub details: Vec<Difference>,
+    #[serde(skip)]
+    max_details: usize,
+}
+
+impl NamespaceDiff {
+    fn new(namespace: Namespace, options: &DiffOptions) -> Self {
+        Self {
+            namespace,
+            matching: 0,
+            mismatched: 0,
+            only_local: 0,
+            only_other: 0,
+            local_count: None,
+            other_count: None,
+            sampled: false,
+            details: vec![],
+            max_details: options.max_details,
+        }
+    }
+
+    /// True if no difference was found between both sides
+    pub fn is_match(&self) -> bool {
+        self.mismatched == 0
+            && self.only_local == 0
+            && self.only_other == 0
+            && self.local_count == self.other_count
+    }
+
+    fn record<T: PartialEq>(
+        &mut self,
+        key: impl std::fmt::Display,
+        local: Option<&T>,
+        other: Option<&T>,
+        render: impl Fn(&T) -> serde_json::Value,
+    ) {
+        match (local, other) {
+            (Some(a), Some(b)) if a == b => {
+                self.matching += 1;
+                return;
+            }
+            (Some(_), Some(_)) => self.mismatched += 1,
+            (Some(_), None) => self.only_local += 1,
+            (None, Some(_)) => self.only_other += 1,
+            (None, None) => return,
+        }
+
+        if self.details.len() < self.max_details {
+            self.details.push(Difference {
+                key: key.to_string(),
+                local: local.map(&render),
+                other: other.map(&render),
+            });
+        }
+    }
+}
+
+fn render_era_cbor(value: &EraCbor) -> serde_json::Value {
+    json!({
+        "era": u16::from(value.0),
+        "cbor": hex::encode(&value.1),
+    })
+}
+
+fn diff_cursor(
+    local: &LedgerStore,
+    other: &LedgerStore,
+    options: &DiffOptions,
+) -> Result<NamespaceDiff, LedgerError> {
+    let mut out = NamespaceDiff::new(Namespace::Cursor, options);
+
+    let a = local.cursor()?;
+    let b = other.cursor()?;
+
+    out.record("cursor", a.as_ref(), b.as_ref(), |x| json!(x.to_string()));
+
+    Ok(out)
+}
+
+fn diff_pparams(
+    local: &LedgerStore,
+    other: &LedgerStore,
+    options: &DiffOptions,
+) -> Result<NamespaceDiff, LedgerError> {
+    let mut out = NamespaceDiff::new(Namespace::PParams, options);
+
+    let a = local.get_pparams(u64::MAX)?;
+    let b = other.get_pparams(u64::MAX)?;
+
+    out.local_count = Some(a.len() as u64);
+    out.other_count = Some(b.len() as u64);
+
+    for (idx, pair) in a.iter().zip_longest(b.iter()).enumerate() {
+        let (a, b) = match pair {
+            EitherOrBoth::Both(a, b) => (Some(a), Some(b)),
+            EitherOrBoth::Left(a) => (Some(a), None),
+            EitherOrBoth::Right(b) => (None, Some(b)),
+        };
+
+        out.record(idx, a, b, render_era_cbor);
+    }
+
+    Ok(out)
+}
+
+fn diff_all_utxos(
+    local: &LedgerStore,
+    other: &LedgerStore,
+    out: &mut NamespaceDiff,
+) -> Result<(), LedgerError> {
+    let a = local.iter_utxos()?;
+    let b = other.iter_utxos()?;
+
+    // both sides are sorted by key, so a single merge pass is enough
+    itertools::process_results(a, |a| {
+        itertools::process_results(b, |b| {
+            let pairs = a.merge_join_by(b, |(x, _), (y, _)| (x.0, x.1).cmp(&(y.0, y.1)));
+
+            for pair in pairs {
+                match pair {
+                    EitherOrBoth::Both((k, a), (_, b)) => {
+                        out.record(k, Some(&a), Some(&b), render_era_cbor)
+                    }
+                    EitherOrBoth::Left((k, a)) => out.record(k, Some(&a), None, render_era_cbor),
+                    EitherOrBoth::Right((k, b)) => out.record(k, None, Some(&b), render_era_cbor),
+                }
+            }
+        })
+    })??;
+
+    Ok(())
+}
+
+/// A tiny splitmix64 generator, good enough to pick random keys
+fn next_random(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9e3779b97f4a7c15);
+
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    z ^ (z >> 31)
+}
+
+fn sample_keys(
+    store: &LedgerStore,
+    count: usize,
+    rng: &mut u64,
+    keys: &mut HashSet<TxoRef>,
+) -> Result<(), LedgerError> {
+    for _ in 0..count {
+        let mut prefix = [0u8; 32];
+
+        for chunk in prefix.chunks_mut(8) {
+            chunk.copy_from_slice(&next_random(rng).to_be_bytes());
+        }
+
+        // wrap around to the first key if the random one is past the last
+        let found = match store.first_utxo_from(&TxoRef(prefix.into(), 0))? {
+            Some(x) => Some(x),
+            None => store.first_utxo_from(&TxoRef([0u8; 32].into(), 0))?,
+        };
+
+        keys.extend(found);
+    }
+
+    Ok(())
+}
+
+fn diff_sampled_utxos(
+    local: &LedgerStore,
+    other: &LedgerStore,
+    count: usize,
+    seed: u64,
+    out: &mut NamespaceDiff,
+) -> Result<(), LedgerError> {
+    let mut rng = seed;
+    let mut keys = HashSet::new();
+
+    // sample from both sides to catch keys that are missing on either one
+    sample_keys(local, count, &mut rng, &mut keys)?;
+    sample_keys(other, count, &mut rng, &mut keys)?;
+
+    let keys = keys.into_iter().sorted_by_key(|x| (x.0, x.1)).collect_vec();
+
+    let a = local.get_utxos(keys.clone())?;
+    let b = other.get_utxos(keys.clone())?;
+
+    for key in keys {
+        out.record(&key, a.get(&key), b.get(&key), render_era_cbor);
+    }
+
+    out.sampled = true;
+
+    Ok(())
+}
+
+fn diff_utxos(
+    local: &LedgerStore,
+    other: &LedgerStore,
+    options: &DiffOptions,
+) -> Result<NamespaceDiff, LedgerError> {
+    let mut out = NamespaceDiff::new(Namespace::Utxos, options);
+
+    out.local_count = Some(local.count_utxos()?);
+    out.other_count = Some(other.count_utxos()?);
+
+    match options.sample {
+        Some(count) => diff_sampled_utxos(local, other, count, options.seed, &mut out)?,
+        None => diff_all_utxos(local, other, &mut out)?,
+    }
+
+    Ok(out)
+}
+
+/// Compares a namespace of two ledger stores
+///
+/// Meant to check whether two instances that synced the same chain ended up
+/// with the same state. The stores can use different schema versions, only
+/// the data shared by all of them is compared.
+pub fn diff(
+    local: &LedgerStore,
+    other: &LedgerStore,
+    namespace: Namespace,
+    options: &DiffOptions,
+) -> Result<NamespaceDiff, LedgerError> {
+    match namespace {
+        Namespace::Cursor => diff_cursor(local, other, options),
+        Namespace::PParams => diff_pparams(local, other, options),
+        Namespace::Utxos => diff_utxos(local, other, options),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pallas::{crypto::hash::Hash, ledger::traverse::Era};
+
+    use super::*;
+    use crate::ledger::{ChainPoint, LedgerDelta};
+
+    fn txo(seed: u8) -> TxoRef {
+        TxoRef(Hash::new([seed; 32]), 0)
+    }
+
+    fn body(seed: u8) -> EraCbor {
+        EraCbor(Era::Babbage, vec![seed])
+    }
+
+    fn toy_store(utxos: &[(u8, u8)]) -> LedgerStore {
+        let store = LedgerStore::in_memory_v2_light().unwrap();
+
+        let delta = LedgerDelta {
+            new_position: Some(ChainPoint(1, Hash::new([1; 32]))),
+            produced_utxo: utxos.iter().map(|(k, v)| (txo(*k), body(*v))).collect(),
+            ..Default::default()
+        };
+
+        store.apply(&[delta]).unwrap();
+
+        store
+    }
+
+    #[test]
+    fn test_equal_stores() {
+        let local = toy_store(&[(1, 1), (2, 2), (3, 3)]);
+        let other = toy_store(&[(1, 1), (2, 2), (3, 3)]);
+
+        for namespace in Namespace::ALL {
+            let diff = diff(&local, &other, namespace, &DiffOptions::default()).unwrap();
+            assert!(diff.is_match(), "{namespace} should match");
+        }
+    }
+
+    #[test]
+    fn test_different_utxos() {
+        let local = toy_store(&[(1, 1), (2, 2), (3, 3)]);
+        let other = toy_store(&[(1, 1), (2, 9), (4, 4)]);
+
+        let options = DiffOptions {
+            max_details: 2,
+            ..Default::default()
+        };
+
+        let diff = diff(&local, &other, Namespace::Utxos, &options).unwrap();
+
+        assert!(!diff.is_match());
+        assert_eq!(diff.matching, 1);
+        assert_eq!(diff.mismatched, 1);
+        assert_eq!(diff.only_local, 1);
+        assert_eq!(diff.only_other, 1);
+
+        // details are bounded and follow key order
+        assert_eq!(diff.details.len(), 2);
+        assert_eq!(diff.details[0].key, txo(2).to_string());
+        assert_eq!(diff.details[1].key, txo(3).to_string());
+        assert_eq!(diff.details[1].other, None);
+
+        let cursor = super::diff(&local, &other, Namespace::Cursor, &options).unwrap();
+        assert!(cursor.is_match());
+    }
+
+    #[test]
+    fn test_sampled_utxos() {
+        let local = toy_store(&[(1, 1), (2, 2), (3, 3)]);
+        let other = toy_store(&[(1, 1), (2, 2), (3, 3), (4, 4)]);
+
+        let options = DiffOptions {
+            sample: Some(20),
+            ..Default::default()
+        };
+
+        let diff = diff(&local, &other, Namespace::Utxos, &options).unwrap();
+
+        assert!(diff.sampled);
+        assert_eq!(diff.mismatched, 0);
+        assert_eq!(diff.only_local, 0);
+
+        // even if the extra key isn't sampled, the counts tell the stores apart
+        assert_eq!(diff.local_count, Some(3));
+        assert_eq!(diff.other_count, Some(4));
+        assert!(!diff.is_match());
+    }
+}
@@ -9,6 +9,7 @@ use thiserror::Error;
 
 use crate::ledger::*;
 
+pub mod diff;
 pub mod redb;
 
 #[derive(Debug, Error)]
@@ -124,6 +125,16 @@ impl LedgerStore {
         }
     }
 
+    /// Sets the durability of ledger updates according to the given mode
+    ///
+    /// Should be called before the store is cloned, clones keep the
+    /// durability they were created with.
+    pub fn set_durability(&mut self, mode: crate::model::DurabilityMode) {
+        match self {
+            LedgerStore::Redb(x) => x.set_durability(mode.ledger()),
+        }
+    }
+
     pub fn apply(&self, deltas: &[LedgerDelta]) -> Result<(), LedgerError> {
         match self {
             LedgerStore::Redb(x) => x.apply(deltas),
@@ -147,6 +158,12 @@ impl LedgerStore {
             (Self::Redb(x), Self::Redb(target)) => x.copy(target),
         }
     }
+
+    pub fn stats(&self) -> Result<Vec<crate::model::TableStats>, LedgerError> {
+        match self {
+            LedgerStore::Redb(x) => Ok(crate::model::TableStats::collect_redb(x.db())?),
+        }
+    }
 }
 
 impl From<redb::LedgerStore> for LedgerStore {
@@ -216,6 +233,7 @@ pub fn load_slice_for_block(
     Ok(LedgerSlice { resolved_inputs })
 }
 
+#[tracing::instrument(level = "debug", skip_all)]
 pub fn apply_block_batch<'a>(
     blocks: impl IntoIterator<Item = &'a MultiEraBlock<'a>>,
     store: &LedgerStore,
@@ -243,3 +261,138 @@ pub fn apply_block_batch<'a>(
 
     Ok(())
 }
+
+/// Applies the genesis utxos to the ledger, starting it from origin
+pub fn apply_origin(store: &LedgerStore, genesis: &Genesis) -> Result<(), LedgerError> {
+    let delta = compute_origin_delta(&genesis.byron);
+    store.apply(&[delta])
+}
+
+/// Reverts the changes of a block, restoring the utxos it consumed
+#[tracing::instrument(level = "debug", skip_all)]
+pub fn undo_block(block: &MultiEraBlock, store: &LedgerStore) -> Result<(), LedgerError> {
+    let context = load_slice_for_block(block, store, &[])?;
+    let delta = compute_undo_delta(block, context).map_err(LedgerError::BrokenInvariant)?;
+
+    store.apply(&[delta])
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools as _;
+    use pallas::ledger::traverse::{Era, MultiEraOutput};
+    use std::io::Read as _;
+
+    use super::*;
+
+    fn load_mainnet_blocks() -> Vec<Vec<u8>> {
+        let path = std::path::PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap())
+            .join("src/ledger/pparams/test_data/mainnet/update_proposal_blocks");
+
+        std::fs::read_dir(path)
+            .unwrap()
+            .map(|x| {
+                let mut buf = vec![];
+                std::fs::File::open(x.unwrap().path())
+                    .unwrap()
+                    .read_to_end(&mut buf)
+                    .unwrap();
+                buf
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_rewind_undoes_last_blocks() {
+        let cbor = load_mainnet_blocks();
+
+        let blocks: Vec<_> = cbor
+            .iter()
+            .map(|x| MultiEraBlock::decode(x).unwrap())
+            .filter(|x| !x.txs().is_empty())
+            .sorted_by_key(|x| x.slot())
+            .take(10)
+            .collect();
+
+        assert_eq!(blocks.len(), 10);
+
+        let store = LedgerStore::Redb(redb::LedgerStore::in_memory_v2_light().unwrap());
+
+        // the blocks aren't consecutive, seed the store with whatever they consume
+        // from outside of the set
+        let produced: HashSet<_> = blocks
+            .iter()
+            .flat_map(|b| b.txs())
+            .flat_map(|tx| {
+                let hash = tx.hash();
+                tx.produces()
+                    .into_iter()
+                    .map(move |(idx, _)| TxoRef(hash, idx as u32))
+            })
+            .collect();
+
+        let seeds: UtxoMap = blocks
+            .iter()
+            .flat_map(|b| b.txs())
+            .flat_map(|tx| tx.consumes())
+            .map(|x| TxoRef(*x.hash(), x.index() as u32))
+            .filter(|x| !produced.contains(x))
+            .map(|x| (x, EraCbor(Era::Byron, vec![])))
+            .collect();
+
+        store
+            .apply(&[LedgerDelta {
+                new_position: Some(ChainPoint(0, pallas::crypto::hash::Hash::new([0; 32]))),
+                produced_utxo: seeds,
+                ..Default::default()
+            }])
+            .unwrap();
+
+        // apply the blocks one by one without finalizing, like the sync does while
+        // they're still mutable
+        for block in blocks.iter() {
+            let context = load_slice_for_block(block, &store, &[]).unwrap();
+            let delta = compute_delta(block, context).unwrap();
+            store.apply(&[delta]).unwrap();
+        }
+
+        let last = blocks.last().unwrap();
+        assert_eq!(
+            store.cursor().unwrap(),
+            Some(ChainPoint(last.slot(), last.hash()))
+        );
+
+        // rewind the last 3 blocks, newest first
+        for block in blocks[7..].iter().rev() {
+            undo_block(block, &store).unwrap();
+        }
+
+        let target = &blocks[6];
+        assert_eq!(
+            store.cursor().unwrap(),
+            Some(ChainPoint(target.slot(), target.hash()))
+        );
+
+        // utxos of the undone blocks are gone, the ones they consumed are back
+        let undone = blocks[7].txs();
+        let undone = &undone[0];
+
+        let produced = TxoRef(undone.hash(), 0);
+        assert!(store.get_utxos(vec![produced]).unwrap().is_empty());
+
+        let inputs = undone.consumes();
+        let consumed = TxoRef(*inputs[0].hash(), inputs[0].index() as u32);
+        assert_eq!(store.get_utxos(vec![consumed]).unwrap().len(), 1);
+
+        // utxos of the blocks before the target are still there
+        let kept = target.txs();
+        let kept = &kept[0];
+
+        let (idx, output) = kept.produces().into_iter().next().unwrap();
+        let kept_ref = TxoRef(kept.hash(), idx as u32);
+
+        let found = store.get_utxos(vec![kept_ref.clone()]).unwrap();
+        let found = MultiEraOutput::try_from(found.get(&kept_ref).unwrap()).unwrap();
+        assert_eq!(found.value().coin(), output.value().coin());
+    }
+}
@@ -1,4 +1,4 @@
-use ::redb::{Database, MultimapTableHandle as _, TableHandle as _};
+use ::redb::{Database, Durability, MultimapTableHandle as _, TableHandle as _};
 use itertools::Itertools;
 use log::info;
 use std::path::Path;
@@ -95,7 +95,9 @@ impl LedgerStore {
             }
             Some(V2_HASH) => {
                 info!("detected state db schema v2");
-                v2::LedgerStore::new(db).into()
+                let store = v2::LedgerStore::new(db);
+                store.cleanup_indexes()?;
+                store.into()
             }
             Some(V2_LIGHT_HASH) => {
                 info!("detected state db schema v2-light");
@@ -172,6 +174,14 @@ impl LedgerStore {
         }
     }
 
+    pub fn set_durability(&mut self, durability: Durability) {
+        match self {
+            LedgerStore::SchemaV1(x) => x.set_durability(durability),
+            LedgerStore::SchemaV2(x) => x.set_durability(durability),
+            LedgerStore::SchemaV2Light(x) => x.set_durability(durability),
+        }
+    }
+
     pub fn cursor(&self) -> Result<Option<ChainPoint>, LedgerError> {
         match self {
             LedgerStore::SchemaV1(x) => Ok(x.cursor()?),
@@ -204,6 +214,30 @@ impl LedgerStore {
         }
     }
 
+    /// Iterates all the utxos in the store, in key order
+    ///
+    /// The utxos table is shared by all schema versions, so this works
+    /// regardless of the version of the store.
+    pub fn iter_utxos(
+        &self,
+    ) -> Result<impl Iterator<Item = Result<(TxoRef, EraCbor), LedgerError>>, LedgerError> {
+        let rx = self.db().begin_read()?;
+        let iter = tables::UtxosTable::iter(&rx)?;
+
+        Ok(iter.map(|x| x.map_err(LedgerError::from)))
+    }
+
+    pub fn count_utxos(&self) -> Result<u64, LedgerError> {
+        let rx = self.db().begin_read()?;
+        tables::UtxosTable::len(&rx)
+    }
+
+    /// Returns the first utxo ref equal or greater than the given one
+    pub fn first_utxo_from(&self, key: &TxoRef) -> Result<Option<TxoRef>, LedgerError> {
+        let rx = self.db().begin_read()?;
+        tables::UtxosTable::first_from(&rx, key)
+    }
+
     pub fn get_utxo_by_address(&self, address: &[u8]) -> Result<UtxoSet, LedgerError> {
         match self {
             LedgerStore::SchemaV2(x) => Ok(x.get_utxos_by_address(address)?),
@@ -336,4 +370,246 @@ mod tests {
         store.apply(&[delta]).unwrap();
         assert!(!store.is_empty().unwrap());
     }
+
+    #[test]
+    fn finalize_after_rollbacks() {
+        use pallas::{crypto::hash::Hash, ledger::traverse::Era};
+
+        let store = LedgerStore::in_memory_v2_light().unwrap();
+
+        let txo = |seed: u8| TxoRef(Hash::new([seed; 32]), 0);
+        let body = |seed: u8| EraCbor(Era::Babbage, vec![seed]);
+        let point = |slot: u64| Some(ChainPoint(slot, Hash::new([slot as u8; 32])));
+
+        let apply = |slot: u64, consumed: Option<u8>, produced: u8| LedgerDelta {
+            new_position: point(slot),
+            consumed_utxo: consumed.map(|x| (txo(x), body(x))).into_iter().collect(),
+            produced_utxo: [(txo(produced), body(produced))].into(),
+            ..Default::default()
+        };
+
+        let undo = |slot: u64, consumed: u8, produced: u8| LedgerDelta {
+            undone_position: point(slot),
+            recovered_stxi: [(txo(consumed), body(consumed))].into(),
+            undone_utxo: [(txo(produced), body(produced))].into(),
+            ..Default::default()
+        };
+
+        let live = |store: &LedgerStore| {
+            let refs = (1..=6).map(txo).collect();
+            let mut out = store.get_utxos(refs).unwrap().into_keys().collect_vec();
+            out.sort_by_key(|x| x.0);
+            out
+        };
+
+        // a few apply / undo cycles over the same utxo
+        store.apply(&[apply(1, None, 1)]).unwrap();
+        store.apply(&[apply(2, Some(1), 2)]).unwrap();
+        store.apply(&[undo(2, 1, 2)]).unwrap();
+        store.apply(&[apply(3, Some(1), 3)]).unwrap();
+        store.apply(&[undo(3, 1, 3)]).unwrap();
+        store.apply(&[apply(4, Some(1), 4)]).unwrap();
+        store.apply(&[apply(5, Some(4), 5)]).unwrap();
+
+        // undone slots don't leave any cursor entry behind
+        let rx = store.db().begin_read().unwrap();
+        let cursors = tables::CursorTable::get_range(&rx, u64::MAX, usize::MAX).unwrap();
+        assert_eq!(cursors.iter().map(|(x, _)| *x).collect_vec(), vec![1, 4, 5]);
+        drop(rx);
+
+        // consumed utxos are kept around while their slot is still mutable
+        assert_eq!(live(&store), vec![txo(1), txo(4), txo(5)]);
+
+        store.finalize(5).unwrap();
+
+        let rx = store.db().begin_read().unwrap();
+        let cursors = tables::CursorTable::get_range(&rx, u64::MAX, usize::MAX).unwrap();
+        assert_eq!(cursors.iter().map(|(x, _)| *x).collect_vec(), vec![5]);
+        drop(rx);
+
+        assert_eq!(live(&store), vec![txo(4), txo(5)]);
+
+        // a rollback within the mutable window still recovers the consumed utxo
+        store.apply(&[undo(5, 4, 5)]).unwrap();
+        assert_eq!(live(&store), vec![txo(4)]);
+    }
+
+    #[test]
+    fn utxos_by_payment_across_stake_parts() {
+        use pallas::{
+            codec::minicbor,
+            crypto::hash::Hash,
+            ledger::{
+                addresses::{Address, ShelleyAddress, ShelleyDelegationPart, ShelleyPaymentPart},
+                primitives::alonzo,
+                traverse::Era,
+            },
+        };
+        use std::str::FromStr;
+
+        let store = LedgerStore::in_memory_v2().unwrap();
+
+        let base = Address::from_str("addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x").unwrap();
+
+        let Address::Shelley(base) = base else {
+            unreachable!()
+        };
+
+        let with_stake = |payment: &ShelleyPaymentPart, stake: ShelleyDelegationPart| {
+            Address::Shelley(ShelleyAddress::new(base.network(), payment.clone(), stake))
+        };
+
+        let unrelated = ShelleyPaymentPart::Key(Hash::new([2; 28]));
+
+        let addresses = [
+            Address::Shelley(base.clone()),
+            with_stake(
+                base.payment(),
+                ShelleyDelegationPart::Key(Hash::new([1; 28])),
+            ),
+            with_stake(base.payment(), ShelleyDelegationPart::Null),
+            with_stake(&unrelated, base.delegation().clone()),
+        ];
+
+        let output = |address: &Address| {
+            let output = alonzo::TransactionOutput {
+                address: address.to_vec().into(),
+                amount: alonzo::Value::Coin(1_000_000),
+                datum_hash: None,
+            };
+
+            EraCbor(Era::Alonzo, minicbor::to_vec(&output).unwrap())
+        };
+
+        let produced = addresses
+            .iter()
+            .enumerate()
+            .map(|(idx, address)| (TxoRef(Hash::new([1; 32]), idx as u32), output(address)))
+            .collect();
+
+        let delta = LedgerDelta {
+            new_position: Some(ChainPoint(1, Hash::new([1; 32]))),
+            produced_utxo: produced,
+            ..Default::default()
+        };
+
+        store.apply(&[delta]).unwrap();
+
+        // the same credential is found regardless of the stake part
+        let found = store.get_utxo_by_payment(&base.payment().to_vec()).unwrap();
+        assert_eq!(found.len(), 3);
+
+        let found = store.get_utxo_by_payment(&unrelated.to_vec()).unwrap();
+        assert_eq!(found.len(), 1);
+    }
+
+    #[test]
+    fn utxos_by_byron_and_enterprise_addresses() {
+        use pallas::{
+            codec::minicbor,
+            crypto::hash::Hash,
+            ledger::{addresses::Address, primitives::alonzo, traverse::Era},
+        };
+        use std::str::FromStr;
+
+        let store = LedgerStore::in_memory_v2().unwrap();
+
+        let byron =
+            Address::from_str("Ae2tdPwUPEZFRbyhz3cpfC2CumGzNkFBN2L42rcUc2yjQpEkxDbkPodpMAi")
+                .unwrap();
+
+        let enterprise =
+            Address::from_str("addr1vx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzers66hrl8")
+                .unwrap();
+
+        let Address::Shelley(shelley) = &enterprise else {
+            unreachable!()
+        };
+
+        let payment = shelley.payment().to_vec();
+
+        let output = |address: &Address| {
+            let output = alonzo::TransactionOutput {
+                address: address.to_vec().into(),
+                amount: alonzo::Value::Coin(1_000_000),
+                datum_hash: None,
+            };
+
+            EraCbor(Era::Alonzo, minicbor::to_vec(&output).unwrap())
+        };
+
+        let utxos: UtxoMap = [
+            (TxoRef(Hash::new([1; 32]), 0), output(&byron)),
+            (TxoRef(Hash::new([1; 32]), 1), output(&enterprise)),
+        ]
+        .into();
+
+        store
+            .apply(&[LedgerDelta {
+                new_position: Some(ChainPoint(1, Hash::new([1; 32]))),
+                produced_utxo: utxos.clone(),
+                ..Default::default()
+            }])
+            .unwrap();
+
+        // both are found by their full address
+        let found = store.get_utxo_by_address(&byron.to_vec()).unwrap();
+        assert_eq!(found.len(), 1);
+
+        let found = store.get_utxo_by_address(&enterprise.to_vec()).unwrap();
+        assert_eq!(found.len(), 1);
+
+        // only the enterprise address has a payment part
+        let found = store.get_utxo_by_payment(&payment).unwrap();
+        assert_eq!(found.len(), 1);
+
+        // neither of them is indexed by stake
+        let found = store.get_utxo_by_stake(&[]).unwrap();
+        assert!(found.is_empty());
+
+        store
+            .apply(&[LedgerDelta {
+                new_position: Some(ChainPoint(2, Hash::new([2; 32]))),
+                consumed_utxo: utxos,
+                ..Default::default()
+            }])
+            .unwrap();
+
+        // consuming them leaves nothing behind
+        assert!(store
+            .get_utxo_by_address(&byron.to_vec())
+            .unwrap()
+            .is_empty());
+        assert!(store
+            .get_utxo_by_address(&enterprise.to_vec())
+            .unwrap()
+            .is_empty());
+        assert!(store.get_utxo_by_payment(&payment).unwrap().is_empty());
+    }
+
+    #[test]
+    fn stale_stake_entries_removed_on_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("ledger");
+
+        let store = LedgerStore::open(&path, None).unwrap();
+
+        // emulate an enterprise utxo indexed by an older version
+        let wx = store.db().begin_write().unwrap();
+        {
+            let mut table = wx
+                .open_multimap_table(tables::FilterIndexes::BY_STAKE)
+                .unwrap();
+
+            let empty: &[u8] = &[];
+            table.insert(empty, (&[1; 32], 0)).unwrap();
+        }
+        wx.commit().unwrap();
+
+        assert_eq!(store.get_utxo_by_stake(&[]).unwrap().len(), 1);
+        drop(store);
+
+        let store = LedgerStore::open(&path, None).unwrap();
+        assert!(store.get_utxo_by_stake(&[]).unwrap().is_empty());
+    }
 }
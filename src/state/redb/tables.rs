@@ -1,5 +1,5 @@
 use ::redb::{MultimapTableDefinition, TableDefinition, WriteTransaction};
-use ::redb::{Range, ReadTransaction, ReadableTable as _, TableError};
+use ::redb::{Range, ReadTransaction, ReadableTable as _, ReadableTableMetadata as _, TableError};
 use itertools::Itertools as _;
 use pallas::{crypto::hash::Hash, ledger::traverse::MultiEraOutput};
 use serde::{Deserialize, Serialize};
@@ -9,6 +9,10 @@ use crate::state::*;
 
 type Error = crate::state::LedgerError;
 
+/// Max number of slots compacted by a single finalize pass, whatever is left
+/// behind is picked up by the next one
+pub const MAX_COMPACT_SLOTS: usize = 10_000;
+
 pub struct BlocksTable;
 
 impl BlocksTable {
@@ -94,6 +98,26 @@ impl UtxosTable {
         Ok(UtxosIterator(range))
     }
 
+    pub fn len(rx: &ReadTransaction) -> Result<u64, Error> {
+        let table = rx.open_table(Self::DEF)?;
+        Ok(table.len()?)
+    }
+
+    /// Returns the first key equal or greater than the given one
+    pub fn first_from(rx: &ReadTransaction, key: &TxoRef) -> Result<Option<TxoRef>, Error> {
+        let table = rx.open_table(Self::DEF)?;
+        let key: (&[u8; 32], u32) = (&key.0, key.1);
+
+        let found = table.range(key..)?.next().transpose()?;
+
+        let found = found.map(|(k, _)| {
+            let (hash, idx) = k.value();
+            TxoRef((*hash).into(), idx)
+        });
+
+        Ok(found)
+    }
+
     pub fn get_sparse(
         rx: &ReadTransaction,
         refs: Vec<TxoRef>,
@@ -232,12 +256,13 @@ impl TombstonesTable {
     pub fn get_range(
         rx: &ReadTransaction,
         until: BlockSlot,
+        max: usize,
     ) -> Result<Vec<(BlockSlot, Vec<TxoRef>)>, Error> {
         let table = rx.open_multimap_table(Self::DEF)?;
 
         let mut out = vec![];
 
-        for entry in table.range(..until)? {
+        for entry in table.range(..until)?.take(max) {
             let (slot, tss) = entry?;
 
             let tss: Vec<_> = tss
@@ -303,12 +328,13 @@ impl CursorTable {
     pub fn get_range(
         rx: &ReadTransaction,
         until: BlockSlot,
+        max: usize,
     ) -> Result<Vec<(BlockSlot, CursorValue)>, Error> {
         let table = rx.open_table(Self::DEF)?;
 
         let mut out = vec![];
 
-        for entry in table.range(..until)? {
+        for entry in table.range(..until)?.take(max) {
             let (slot, value) = entry?;
             let value = bincode::deserialize(value.value()).unwrap();
 
@@ -449,29 +475,42 @@ impl FilterIndexes {
         Self::get_by_key(rx, Self::BY_ASSET, asset)
     }
 
-    fn split_address(utxo: &MultiEraOutput) -> Result<SplitAddressResult, Error> {
-        use pallas::ledger::addresses::Address;
-
-        match utxo.address() {
-            Ok(address) => match &address {
-                Address::Shelley(x) => {
-                    let a = x.to_vec();
-                    let b = x.payment().to_vec();
-                    let c = x.delegation().to_vec();
-                    Ok(SplitAddressResult(Some(a), Some(b), Some(c)))
-                }
-                Address::Stake(x) => {
-                    let a = x.to_vec();
-                    let c = x.to_vec();
-                    Ok(SplitAddressResult(Some(a), None, Some(c)))
-                }
-                Address::Byron(x) => {
-                    let a = x.to_vec();
-                    Ok(SplitAddressResult(Some(a), None, None))
-                }
-            },
-            Err(err) => Err(err.into()),
+    /// Removes the entries indexed under an empty stake key
+    ///
+    /// Older versions indexed enterprise addresses with their null delegation
+    /// part, which ends up as an empty key in the stake index. Those entries are
+    /// no longer maintained, so they're dropped instead of going stale.
+    pub fn remove_empty_stake_key(wx: &WriteTransaction) -> Result<u64, Error> {
+        let mut table = wx.open_multimap_table(Self::BY_STAKE)?;
+
+        let empty: &[u8] = &[];
+        let mut removed = 0;
+
+        for item in table.remove_all(empty)? {
+            item?;
+            removed += 1;
         }
+
+        Ok(removed)
+    }
+
+    fn split_address(utxo: &MultiEraOutput) -> Result<SplitAddressResult, Error> {
+        use crate::ledger::address::{classify_address, AddressKind};
+
+        let address = utxo.address()?;
+        let full = Some(address.to_vec());
+
+        // Byron addresses fall back to the full address dimension, we don't index
+        // payment or stake parts for them.
+        let out = match classify_address(&address) {
+            AddressKind::Shelley { payment, stake } => {
+                SplitAddressResult(full, Some(payment), stake)
+            }
+            AddressKind::Stake(stake) => SplitAddressResult(full, None, Some(stake)),
+            AddressKind::Byron => SplitAddressResult(full, None, None),
+        };
+
+        Ok(out)
     }
 
     pub fn apply(wx: &WriteTransaction, delta: &LedgerDelta) -> Result<(), Error> {
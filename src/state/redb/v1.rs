@@ -7,7 +7,7 @@ type Error = crate::state::LedgerError;
 use super::tables;
 
 #[derive(Clone)]
-pub struct LedgerStore(pub Arc<Database>);
+pub struct LedgerStore(pub Arc<Database>, Durability);
 
 impl LedgerStore {
     pub fn initialize(db: Database) -> Result<Self, Error> {
@@ -32,6 +32,11 @@ impl LedgerStore {
         Arc::get_mut(&mut self.0)
     }
 
+    /// Sets the durability used when committing ledger updates
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.1 = durability;
+    }
+
     pub fn is_empty(&self) -> Result<bool, Error> {
         Ok(self.cursor()?.is_none())
     }
@@ -43,7 +48,7 @@ impl LedgerStore {
 
     pub fn apply(&self, deltas: &[LedgerDelta]) -> Result<(), Error> {
         let mut wx = self.db().begin_write()?;
-        wx.set_durability(Durability::Eventual);
+        wx.set_durability(self.1);
 
         for delta in deltas {
             tables::UtxosTable::apply(&wx, delta)?;
@@ -59,10 +64,10 @@ impl LedgerStore {
 
     pub fn finalize(&self, until: BlockSlot) -> Result<(), Error> {
         let rx = self.db().begin_read()?;
-        let tss = tables::TombstonesTable::get_range(&rx, until)?;
+        let tss = tables::TombstonesTable::get_range(&rx, until, tables::MAX_COMPACT_SLOTS)?;
 
         let mut wx = self.db().begin_write()?;
-        wx.set_durability(Durability::Eventual);
+        wx.set_durability(self.1);
 
         for ts in tss {
             let (slot, txos) = ts;
@@ -93,6 +98,6 @@ impl LedgerStore {
 
 impl From<Database> for LedgerStore {
     fn from(value: Database) -> Self {
-        Self(Arc::new(value))
+        Self(Arc::new(value), Durability::Eventual)
     }
 }
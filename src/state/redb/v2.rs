@@ -1,5 +1,6 @@
 use ::redb::{Database, Durability};
 use std::sync::Arc;
+use tracing::info;
 
 use crate::state::*;
 type Error = crate::state::LedgerError;
@@ -7,11 +8,11 @@ type Error = crate::state::LedgerError;
 use super::tables;
 
 #[derive(Clone)]
-pub struct LedgerStore(Arc<Database>);
+pub struct LedgerStore(Arc<Database>, Durability);
 
 impl LedgerStore {
     pub fn new(db: Database) -> Self {
-        LedgerStore(db.into())
+        LedgerStore(db.into(), Durability::Eventual)
     }
 
     pub(crate) fn db(&self) -> &Database {
@@ -22,6 +23,11 @@ impl LedgerStore {
         Arc::get_mut(&mut self.0)
     }
 
+    /// Sets the durability used when committing ledger updates
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.1 = durability;
+    }
+
     pub fn initialize(db: Database) -> Result<Self, Error> {
         let mut wx = db.begin_write()?;
         wx.set_durability(Durability::Immediate);
@@ -33,7 +39,31 @@ impl LedgerStore {
 
         wx.commit()?;
 
-        Ok(Self(db.into()))
+        Ok(Self(db.into(), Durability::Eventual))
+    }
+
+    /// Drops index entries written by older versions that are no longer
+    /// maintained
+    pub fn cleanup_indexes(&self) -> Result<(), Error> {
+        let rx = self.db().begin_read()?;
+        let stale = tables::FilterIndexes::get_by_stake(&rx, &[])?;
+        drop(rx);
+
+        // avoid a write on every open once the store is clean
+        if stale.is_empty() {
+            return Ok(());
+        }
+
+        let mut wx = self.db().begin_write()?;
+        wx.set_durability(Durability::Immediate);
+
+        let removed = tables::FilterIndexes::remove_empty_stake_key(&wx)?;
+
+        wx.commit()?;
+
+        info!(removed, "removed stale entries from the stake index");
+
+        Ok(())
     }
 
     pub fn is_empty(&self) -> Result<bool, Error> {
@@ -50,7 +80,7 @@ impl LedgerStore {
 
     pub fn apply(&self, deltas: &[LedgerDelta]) -> Result<(), Error> {
         let mut wx = self.db().begin_write()?;
-        wx.set_durability(Durability::Eventual);
+        wx.set_durability(self.1);
 
         for delta in deltas {
             tables::CursorTable::apply(&wx, delta)?;
@@ -66,10 +96,10 @@ impl LedgerStore {
 
     pub fn finalize(&self, until: BlockSlot) -> Result<(), Error> {
         let rx = self.db().begin_read()?;
-        let cursors = tables::CursorTable::get_range(&rx, until)?;
+        let cursors = tables::CursorTable::get_range(&rx, until, tables::MAX_COMPACT_SLOTS)?;
 
         let mut wx = self.db().begin_write()?;
-        wx.set_durability(Durability::Eventual);
+        wx.set_durability(self.1);
 
         for (slot, value) in cursors {
             tables::CursorTable::compact(&wx, slot)?;
@@ -8,11 +8,11 @@ type Error = crate::state::LedgerError;
 use super::tables;
 
 #[derive(Clone)]
-pub struct LedgerStore(Arc<Database>);
+pub struct LedgerStore(Arc<Database>, Durability);
 
 impl LedgerStore {
     pub fn new(db: Database) -> Self {
-        LedgerStore(db.into())
+        LedgerStore(db.into(), Durability::Eventual)
     }
 
     pub(crate) fn db(&self) -> &Database {
@@ -23,6 +23,11 @@ impl LedgerStore {
         Arc::get_mut(&mut self.0)
     }
 
+    /// Sets the durability used when committing ledger updates
+    pub fn set_durability(&mut self, durability: Durability) {
+        self.1 = durability;
+    }
+
     pub fn initialize(db: Database) -> Result<Self, Error> {
         let mut wx = db.begin_write()?;
         wx.set_durability(Durability::Immediate);
@@ -33,7 +38,7 @@ impl LedgerStore {
 
         wx.commit()?;
 
-        Ok(Self(db.into()))
+        Ok(Self(db.into(), Durability::Eventual))
     }
 
     pub fn is_empty(&self) -> Result<bool, Error> {
@@ -50,7 +55,7 @@ impl LedgerStore {
 
     pub fn apply(&self, deltas: &[LedgerDelta]) -> Result<(), Error> {
         let mut wx = self.db().begin_write()?;
-        wx.set_durability(Durability::Eventual);
+        wx.set_durability(self.1);
 
         for delta in deltas {
             tables::CursorTable::apply(&wx, delta)?;
@@ -65,10 +70,10 @@ impl LedgerStore {
 
     pub fn finalize(&self, until: BlockSlot) -> Result<(), Error> {
         let rx = self.db().begin_read()?;
-        let cursors = tables::CursorTable::get_range(&rx, until)?;
+        let cursors = tables::CursorTable::get_range(&rx, until, tables::MAX_COMPACT_SLOTS)?;
 
         let mut wx = self.db().begin_write()?;
-        wx.set_durability(Durability::Eventual);
+        wx.set_durability(self.1);
 
         for (slot, value) in cursors {
             tables::CursorTable::compact(&wx, slot)?;
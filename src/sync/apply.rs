@@ -1,8 +1,9 @@
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use gasket::framework::*;
 use pallas::ledger::traverse::MultiEraBlock;
-use tracing::{debug, info};
+use tracing::{debug, field::Empty, info, info_span, warn, Span};
 
 use crate::ledger::pparams::Genesis;
 use crate::wal::{self, LogValue, WalReader as _};
@@ -16,7 +17,8 @@ pub struct Stage {
     wal: crate::wal::redb::WalStore,
     ledger: crate::state::LedgerStore,
     genesis: Arc<Genesis>,
-    mempool: crate::mempool::Mempool, // Add this line
+    mempool: crate::mempool::Mempool,
+    slow_block_threshold: Option<Duration>,
 
     pub upstream: UpstreamPort,
 
@@ -33,12 +35,14 @@ impl Stage {
         ledger: crate::state::LedgerStore,
         mempool: crate::mempool::Mempool,
         genesis: Arc<Genesis>,
+        slow_block_threshold: Option<Duration>,
     ) -> Self {
         Self {
             wal,
             ledger,
             mempool,
             genesis,
+            slow_block_threshold,
             upstream: Default::default(),
             block_count: Default::default(),
             wal_count: Default::default(),
@@ -48,8 +52,7 @@ impl Stage {
     fn process_origin(&self) -> Result<(), WorkerError> {
         info!("applying origin");
 
-        let delta = crate::ledger::compute_origin_delta(&self.genesis.byron);
-        self.ledger.apply(&[delta]).or_panic()?;
+        crate::state::apply_origin(&self.ledger, &self.genesis).or_panic()?;
 
         Ok(())
     }
@@ -60,12 +63,20 @@ impl Stage {
         info!(slot, "undoing block");
 
         let block = MultiEraBlock::decode(body).or_panic()?;
-        let context = crate::state::load_slice_for_block(&block, &self.ledger, &[]).or_panic()?;
 
-        let delta = crate::ledger::compute_undo_delta(&block, context).or_panic()?;
-        self.ledger.apply(&[delta]).or_panic()?;
+        let start = Instant::now();
+        crate::state::undo_block(&block, &self.ledger).or_panic()?;
+        let ledger = start.elapsed();
 
+        let start = Instant::now();
         self.mempool.undo_block(&block);
+        let mempool = start.elapsed();
+
+        report_timings(
+            *slot,
+            &Timings { ledger, mempool },
+            self.slow_block_threshold,
+        );
 
         Ok(())
     }
@@ -77,14 +88,27 @@ impl Stage {
 
         let block = MultiEraBlock::decode(body).or_panic()?;
 
+        let start = Instant::now();
         crate::state::apply_block_batch([&block], &self.ledger, &self.genesis).or_panic()?;
+        let ledger = start.elapsed();
 
+        let start = Instant::now();
         self.mempool.apply_block(&block);
+        let mempool = start.elapsed();
+
+        report_timings(
+            *slot,
+            &Timings { ledger, mempool },
+            self.slow_block_threshold,
+        );
 
         Ok(())
     }
 
-    fn process_wal(&mut self, log: wal::LogValue) -> Result<(), WorkerError> {
+    fn process_wal(&mut self, seq: wal::LogSeq, log: wal::LogValue) -> Result<(), WorkerError> {
+        let span = entry_span(seq, &log);
+        let _guard = span.enter();
+
         match log {
             LogValue::Mark(wal::ChainPoint::Origin) => self.process_origin(),
             LogValue::Apply(x) => self.process_apply(&x),
@@ -95,6 +119,58 @@ impl Stage {
     }
 }
 
+/// Time spent by each of the components while processing a block
+#[derive(Debug, Default)]
+struct Timings {
+    ledger: Duration,
+    mempool: Duration,
+}
+
+impl Timings {
+    fn total(&self) -> Duration {
+        self.ledger + self.mempool
+    }
+}
+
+/// Creates the span that groups everything logged while processing a WAL
+/// entry. Timings are recorded into the span once the block is processed.
+fn entry_span(seq: wal::LogSeq, log: &wal::LogValue) -> Span {
+    match log {
+        LogValue::Apply(x) => info_span!(
+            "apply_block",
+            seq,
+            slot = x.slot,
+            ledger_ms = Empty,
+            mempool_ms = Empty
+        ),
+        LogValue::Undo(x) => info_span!(
+            "undo_block",
+            seq,
+            slot = x.slot,
+            ledger_ms = Empty,
+            mempool_ms = Empty
+        ),
+        LogValue::Mark(_) => info_span!("mark", seq),
+    }
+}
+
+fn report_timings(slot: BlockSlot, timings: &Timings, slow_threshold: Option<Duration>) {
+    let ledger_ms = timings.ledger.as_millis() as u64;
+    let mempool_ms = timings.mempool.as_millis() as u64;
+    let total_ms = timings.total().as_millis() as u64;
+
+    let span = Span::current();
+    span.record("ledger_ms", ledger_ms);
+    span.record("mempool_ms", mempool_ms);
+
+    match slow_threshold {
+        Some(threshold) if timings.total() > threshold => {
+            warn!(slot, ledger_ms, mempool_ms, total_ms, "slow block");
+        }
+        _ => debug!(slot, total_ms, "block processed"),
+    }
+}
+
 pub struct Worker(wal::LogSeq);
 
 #[async_trait::async_trait(?Send)]
@@ -133,15 +209,299 @@ impl gasket::framework::Worker<Stage> for Worker {
     async fn execute(&mut self, _: &(), stage: &mut Stage) -> Result<(), WorkerError> {
         let iter = stage.wal.crawl_from(Some(self.0)).or_panic()?.skip(1);
 
+        let span = info_span!("apply_batch", from_seq = self.0, entries = Empty);
+        let _guard = span.enter();
+
+        let mut entries = 0u64;
+
         // TODO: analyze scenario where we're too far behind and this for loop takes
         // longer that the allocated policy timeout.
 
         for (seq, log) in iter {
             debug!(seq, "processing wal entry");
-            stage.process_wal(log)?;
+            stage.process_wal(seq, log)?;
             self.0 = seq;
+            entries += 1;
         }
 
+        span.record("entries", entries);
+
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use tracing::{field::Field, span, Event, Level, Subscriber};
+    use tracing_subscriber::{
+        layer::{Context, SubscriberExt as _},
+        registry::LookupSpan,
+        Layer,
+    };
+
+    use super::*;
+
+    /// Fields of a span or event, formatted with their debug representation
+    #[derive(Clone, Debug, Default)]
+    struct Fields(HashMap<&'static str, String>);
+
+    impl Fields {
+        fn get(&self, name: &str) -> Option<&str> {
+            self.0.get(name).map(String::as_str)
+        }
+    }
+
+    impl tracing::field::Visit for Fields {
+        fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+            self.0.insert(field.name(), format!("{value:?}"));
+        }
+    }
+
+    #[derive(Debug)]
+    struct Captured {
+        level: Level,
+        fields: Fields,
+        spans: Vec<(&'static str, Fields)>,
+    }
+
+    /// Keeps the fields of every event together with the spans it's nested in,
+    /// and the fields of every span once it's closed
+    #[derive(Clone, Default)]
+    struct Capture {
+        events: Arc<Mutex<Vec<Captured>>>,
+        closed: Arc<Mutex<Vec<(&'static str, Fields)>>>,
+    }
+
+    impl<S> Layer<S> for Capture
+    where
+        S: Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+            let mut fields = Fields::default();
+            attrs.record(&mut fields);
+
+            if let Some(span) = ctx.span(id) {
+                span.extensions_mut().insert(fields);
+            }
+        }
+
+        fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+            if let Some(span) = ctx.span(id) {
+                if let Some(fields) = span.extensions_mut().get_mut::<Fields>() {
+                    values.record(fields);
+                }
+            }
+        }
+
+        fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+            let mut fields = Fields::default();
+            event.record(&mut fields);
+
+            let spans = ctx
+                .event_scope(event)
+                .map(|scope| {
+                    scope
+                        .from_root()
+                        .map(|span| {
+                            let fields = span.extensions().get::<Fields>().cloned();
+                            (span.name(), fields.unwrap_or_default())
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            self.events.lock().unwrap().push(Captured {
+                level: *event.metadata().level(),
+                fields,
+                spans,
+            });
+        }
+
+        fn on_close(&self, id: span::Id, ctx: Context<'_, S>) {
+            if let Some(span) = ctx.span(&id) {
+                let fields = span.extensions().get::<Fields>().cloned();
+
+                self.closed
+                    .lock()
+                    .unwrap()
+                    .push((span.name(), fields.unwrap_or_default()));
+            }
+        }
+    }
+
+    fn block_log(slot: BlockSlot) -> LogValue {
+        LogValue::Apply(crate::wal::testing::dummy_block_from_slot(slot))
+    }
+
+    #[test]
+    fn test_slow_block_warning() {
+        let capture = Capture::default();
+        let subscriber = tracing_subscriber::registry().with(capture.clone());
+
+        let threshold = Some(Duration::from_millis(20));
+
+        tracing::subscriber::with_default(subscriber, || {
+            let batch = info_span!("apply_batch");
+            let _batch = batch.enter();
+
+            let slow = Timings {
+                ledger: Duration::from_millis(30),
+                mempool: Duration::from_millis(5),
+            };
+
+            let span = entry_span(1, &block_log(10));
+            span.in_scope(|| report_timings(10, &slow, threshold));
+
+            let span = entry_span(2, &block_log(11));
+            span.in_scope(|| report_timings(11, &Timings::default(), threshold));
+        });
+
+        let events = capture.events.lock().unwrap();
+
+        let slow: Vec<_> = events
+            .iter()
+            .filter(|x| x.fields.get("message") == Some("slow block"))
+            .collect();
+
+        assert_eq!(slow.len(), 1);
+
+        // the warning carries the breakdown of the timings
+        let warning = slow[0];
+        assert_eq!(warning.level, Level::WARN);
+        assert_eq!(warning.fields.get("slot"), Some("10"));
+        assert_eq!(warning.fields.get("ledger_ms"), Some("30"));
+        assert_eq!(warning.fields.get("mempool_ms"), Some("5"));
+        assert_eq!(warning.fields.get("total_ms"), Some("35"));
+
+        // and it's nested within the batch and block spans, the latter with the
+        // timings recorded
+        let names: Vec<_> = warning.spans.iter().map(|(name, _)| *name).collect();
+        assert_eq!(names, ["apply_batch", "apply_block"]);
+
+        let block = &warning.spans[1].1;
+        assert_eq!(block.get("seq"), Some("1"));
+        assert_eq!(block.get("slot"), Some("10"));
+        assert_eq!(block.get("ledger_ms"), Some("30"));
+        assert_eq!(block.get("mempool_ms"), Some("5"));
+
+        // fast blocks are only reported at debug level
+        let processed: Vec<_> = events
+            .iter()
+            .filter(|x| x.fields.get("message") == Some("block processed"))
+            .collect();
+
+        assert_eq!(processed.len(), 1);
+        assert_eq!(processed[0].level, Level::DEBUG);
+        assert_eq!(processed[0].fields.get("slot"), Some("11"));
+    }
+
+    fn load_json<T>(path: &str) -> T
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let file = std::fs::File::open(path).unwrap();
+        serde_json::from_reader(file).unwrap()
+    }
+
+    fn test_genesis() -> Arc<Genesis> {
+        let test_data = "src/ledger/pparams/test_data/mainnet/genesis";
+
+        Arc::new(Genesis {
+            byron: load_json(&format!("{test_data}/byron_genesis.json")),
+            shelley: load_json(&format!("{test_data}/shelley_genesis.json")),
+            alonzo: load_json(&format!("{test_data}/alonzo_genesis.json")),
+            conway: load_json(&format!("{test_data}/conway_genesis.json")),
+            force_protocol: None,
+            cost_model_tolerance: Default::default(),
+        })
+    }
+
+    fn spans_of(event: &Captured) -> Vec<&'static str> {
+        event.spans.iter().map(|(name, _)| *name).collect()
+    }
+
+    #[tokio::test]
+    async fn test_entries_processed_within_spans() {
+        use crate::wal::{testing, WalWriter as _};
+        use gasket::framework::Worker as _;
+
+        // a block that is applied and then rolled back
+        let block = testing::valid_dummy_block();
+
+        let mut wal = testing::empty_db();
+        wal.roll_forward(std::iter::once(block.clone())).unwrap();
+        wal.roll_back(&wal::ChainPoint::Origin).unwrap();
+
+        let genesis = test_genesis();
+        let ledger: crate::state::LedgerStore = crate::state::redb::LedgerStore::in_memory_v2()
+            .unwrap()
+            .into();
+
+        let mempool =
+            crate::mempool::Mempool::new(genesis.clone(), ledger.clone(), &Default::default());
+
+        let mut stage = Stage::new(wal, ledger, mempool, genesis, None);
+
+        let capture = Capture::default();
+        let subscriber = tracing_subscriber::registry().with(capture.clone());
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let mut worker = Worker(0);
+        assert!(worker.execute(&(), &mut stage).await.is_ok());
+
+        // origin mark, apply, undo and the mark of the rollback
+        assert_eq!(worker.0, 3);
+
+        let events = capture.events.lock().unwrap();
+
+        let find = |message: &str| {
+            events
+                .iter()
+                .find(|x| x.fields.get("message") == Some(message))
+                .unwrap()
+        };
+
+        let applying = find("applying block");
+        assert_eq!(spans_of(applying), ["apply_batch", "apply_block"]);
+
+        let undoing = find("undoing block");
+        assert_eq!(spans_of(undoing), ["apply_batch", "undo_block"]);
+
+        // the timings of each entry are recorded into its own span
+        let processed: Vec<_> = events
+            .iter()
+            .filter(|x| x.fields.get("message") == Some("block processed"))
+            .collect();
+
+        assert_eq!(processed.len(), 2);
+
+        let slot = block.slot.to_string();
+
+        for (event, (name, seq)) in processed
+            .iter()
+            .zip([("apply_block", "1"), ("undo_block", "2")])
+        {
+            let (span, fields) = &event.spans[1];
+
+            assert_eq!(*span, name);
+            assert_eq!(fields.get("seq"), Some(seq));
+            assert_eq!(fields.get("slot"), Some(slot.as_str()));
+            assert!(fields.get("ledger_ms").is_some());
+            assert!(fields.get("mempool_ms").is_some());
+        }
+
+        // the batch span reports how many entries were processed
+        let closed = capture.closed.lock().unwrap();
+
+        let (_, batch) = closed
+            .iter()
+            .find(|(name, _)| *name == "apply_batch")
+            .unwrap();
+
+        assert_eq!(batch.get("from_seq"), Some("0"));
+        assert_eq!(batch.get("entries"), Some("3"));
+    }
+}
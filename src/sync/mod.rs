@@ -2,24 +2,30 @@ use crate::ledger::pparams::Genesis;
 use crate::state::LedgerStore;
 use crate::wal::redb::WalStore;
 use crate::{mempool::Mempool, prelude::*};
+use itertools::Itertools as _;
 use serde::{Deserialize, Serialize};
 use std::sync::Arc;
 use std::time::Duration;
 
 pub mod apply;
 pub mod pull;
+pub mod reconcile;
 pub mod roll;
 pub mod submit;
 
 #[derive(Serialize, Deserialize)]
 pub struct Config {
     pub pull_batch_size: Option<usize>,
+
+    /// Blocks that take longer than this to apply are logged as warnings
+    pub slow_block_threshold_ms: Option<u64>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             pull_batch_size: Some(100),
+            slow_block_threshold_ms: None,
         }
     }
 }
@@ -44,10 +50,26 @@ fn define_gasket_policy(config: &Option<gasket::retries::Policy>) -> gasket::run
     }
 }
 
+/// Makes sure the upstream peer is expected to be on the same network that
+/// the genesis files describe, before anything is pulled from it
+fn check_network_magic(upstream: &UpstreamConfig, genesis: &Genesis) -> Result<(), Error> {
+    let expected = genesis.network_magic();
+
+    if upstream.network_magic != expected {
+        return Err(Error::config(format!(
+            "upstream network magic {} doesn't match the genesis network magic {}",
+            upstream.network_magic, expected
+        )));
+    }
+
+    Ok(())
+}
+
 #[allow(clippy::too_many_arguments)]
 pub fn pipeline(
     config: &Config,
     upstream: &UpstreamConfig,
+    submit: &SubmitConfig,
     wal: WalStore,
     ledger: LedgerStore,
     genesis: Arc<Genesis>,
@@ -55,8 +77,17 @@ pub fn pipeline(
     retries: &Option<gasket::retries::Policy>,
     quit_on_tip: bool,
 ) -> Result<Vec<gasket::runtime::Tether>, Error> {
+    check_network_magic(upstream, &genesis)?;
+
+    let peers = std::iter::once(&upstream.peer_address)
+        .chain(upstream.fallback_peers.iter())
+        .unique()
+        .cloned()
+        .collect();
+
     let mut pull = pull::Stage::new(
-        upstream.peer_address.clone(),
+        peers,
+        upstream.peer_timeout.map(Duration::from_secs),
         upstream.network_magic,
         config.pull_batch_size.unwrap_or(50),
         wal.clone(),
@@ -65,14 +96,22 @@ pub fn pipeline(
 
     let mut roll = roll::Stage::new(wal.clone());
 
-    let mut apply = apply::Stage::new(wal.clone(), ledger, mempool.clone(), genesis);
-
-    let submit = submit::Stage::new(
-        upstream.peer_address.clone(),
-        upstream.network_magic,
-        mempool,
+    let mut apply = apply::Stage::new(
+        wal.clone(),
+        ledger,
+        mempool.clone(),
+        genesis,
+        config.slow_block_threshold_ms.map(Duration::from_millis),
     );
 
+    // txs are propagated to the upstream peer and to any additional peer defined
+    // in the submit config, each one of them handled by its own stage.
+    let submit: Vec<_> = std::iter::once(&upstream.peer_address)
+        .chain(submit.propagation_peers.iter())
+        .unique()
+        .map(|peer| submit::Stage::new(peer.clone(), upstream.network_magic, mempool.clone()))
+        .collect();
+
     let (to_roll, from_pull) = gasket::messaging::tokio::mpsc_channel(50);
     pull.downstream.connect(to_roll);
     roll.upstream.connect(from_pull);
@@ -89,7 +128,53 @@ pub fn pipeline(
     let pull = gasket::runtime::spawn_stage(pull, policy.clone());
     let roll = gasket::runtime::spawn_stage(roll, policy.clone());
     let apply = gasket::runtime::spawn_stage(apply, policy.clone());
-    let submit = gasket::runtime::spawn_stage(submit, policy.clone());
+    let submit = submit
+        .into_iter()
+        .map(|x| gasket::runtime::spawn_stage(x, policy.clone()));
+
+    Ok([pull, roll, apply].into_iter().chain(submit).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load_json<T: serde::de::DeserializeOwned>(name: &str) -> T {
+        let path = format!("src/ledger/pparams/test_data/mainnet/genesis/{name}");
+        let file = std::fs::File::open(path).unwrap();
+        serde_json::from_reader(file).unwrap()
+    }
+
+    fn mainnet_genesis() -> Genesis {
+        Genesis {
+            byron: load_json("byron_genesis.json"),
+            shelley: load_json("shelley_genesis.json"),
+            alonzo: load_json("alonzo_genesis.json"),
+            conway: load_json("conway_genesis.json"),
+            force_protocol: None,
+            cost_model_tolerance: Default::default(),
+        }
+    }
 
-    Ok(vec![pull, roll, apply, submit])
+    fn upstream(network_magic: u64) -> UpstreamConfig {
+        UpstreamConfig {
+            peer_address: "relay:3001".into(),
+            network_magic,
+            is_testnet: false,
+            fallback_peers: vec![],
+            peer_timeout: None,
+        }
+    }
+
+    #[test]
+    fn test_network_magic_mismatch() {
+        let genesis = mainnet_genesis();
+        assert_eq!(genesis.network_magic(), 764824073);
+
+        assert!(check_network_magic(&upstream(764824073), &genesis).is_ok());
+
+        // a preprod upstream with mainnet genesis
+        let err = check_network_magic(&upstream(1), &genesis).unwrap_err();
+        assert!(matches!(err, Error::ConfigError(_)));
+    }
 }
@@ -6,7 +6,11 @@ use pallas::network::miniprotocols::chainsync::{
     HeaderContent, NextResponse, RollbackBuffer, RollbackEffect, Tip,
 };
 use pallas::network::miniprotocols::Point;
-use tracing::{debug, info};
+use std::fmt::Display;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use thiserror::Error;
+use tracing::{debug, info, warn};
 
 use crate::prelude::*;
 use crate::wal::redb::WalStore;
@@ -23,6 +27,82 @@ fn to_traverse(header: &HeaderContent) -> Result<MultiEraHeader<'_>, WorkerError
 
 pub type DownstreamPort = gasket::messaging::OutputPort<PullEvent>;
 
+#[derive(Debug, Error)]
+pub enum BodyMismatch {
+    #[error("peer didn't send any block for the announced range")]
+    Empty,
+
+    #[error("block body can't be decoded: {0}")]
+    Decoding(#[from] pallas::ledger::traverse::Error),
+
+    #[error("block {found} doesn't match announced header {expected:?}")]
+    UnexpectedHash { expected: Point, found: BlockHash },
+
+    #[error("block {found} doesn't follow block {parent}")]
+    BrokenChain { parent: BlockHash, found: BlockHash },
+}
+
+fn point_hash(point: &Point) -> Option<BlockHash> {
+    match point {
+        Point::Specific(_, hash) => Some(BlockHash::from(hash.as_slice())),
+        Point::Origin => None,
+    }
+}
+
+/// Checks that the fetched block bodies match the headers announced by
+/// chainsync
+///
+/// The first and last bodies must hash to the bounds of the announced range
+/// and each body must point to the previous one, starting from the parent we
+/// already hold (if known). Hashes are computed by pallas, which takes care of
+/// the different rules for Byron EBBs. Returns the hash of the last block.
+pub fn verify_bodies(
+    bodies: &[BlockBody],
+    start: &Point,
+    end: &Point,
+    parent: Option<BlockHash>,
+) -> Result<BlockHash, BodyMismatch> {
+    let mut parent = parent;
+    let mut first = None;
+
+    for body in bodies {
+        let block = MultiEraBlock::decode(body)?;
+        let hash = block.hash();
+
+        if let Some(parent) = parent {
+            if block.header().previous_hash() != Some(parent) {
+                return Err(BodyMismatch::BrokenChain {
+                    parent,
+                    found: hash,
+                });
+            }
+        }
+
+        first.get_or_insert(hash);
+        parent = Some(hash);
+    }
+
+    let (Some(first), Some(last)) = (first, parent) else {
+        return Err(BodyMismatch::Empty);
+    };
+
+    if point_hash(start) != Some(first) {
+        return Err(BodyMismatch::UnexpectedHash {
+            expected: start.clone(),
+            found: first,
+        });
+    }
+
+    if point_hash(end) != Some(last) {
+        return Err(BodyMismatch::UnexpectedHash {
+            expected: end.clone(),
+            found: last,
+        });
+    }
+
+    Ok(last)
+}
+
 enum PullBatch {
     BlockRange(Point, Point),
     OutOfScopeRollback(Point),
@@ -36,16 +116,22 @@ pub enum WorkUnit {
 
 pub struct Worker {
     peer_session: PeerClient,
+    peer_idx: usize,
     quit_on_tip: bool,
+
+    /// Hash of the last block sent downstream, used to check the linkage of the
+    /// next one
+    parent: Option<BlockHash>,
 }
 
 impl Worker {
     async fn gather_pull_batch(&mut self, stage: &mut Stage) -> Result<PullBatch, WorkerError> {
+        let peer_idx = self.peer_idx;
         let client = self.peer_session.chainsync();
         let mut buffer = RollbackBuffer::new();
 
         while buffer.size() < stage.block_fetch_batch_size {
-            let next = client.request_next().await.or_restart()?;
+            let next = stage.or_failover(peer_idx, client.request_next().await)?;
 
             match next {
                 NextResponse::RollForward(header, tip) => {
@@ -85,35 +171,20 @@ impl gasket::framework::Worker<Stage> for Worker {
             .map(From::from)
             .collect_vec();
 
-        debug!("connecting to peer");
-
-        let mut peer_session = PeerClient::connect(&stage.peer_address, stage.network_magic)
-            .await
-            .or_retry()?;
-
-        info!(
-            address = stage.peer_address,
-            magic = stage.network_magic,
-            "connected to peer"
-        );
+        let (peer_idx, mut peer_session) = stage.connect_any_peer().await?;
 
         debug!("finding intersect");
 
-        let (point, _) = peer_session
-            .chainsync()
-            .find_intersect(candidates)
-            .await
-            .or_restart()?;
-
-        let intersection = point
-            .ok_or(Error::message("couldn't find intersect"))
-            .or_panic()?;
+        let found = peer_session.chainsync().find_intersect(candidates).await;
+        let intersection = stage.resolve_intersection(peer_idx, found)?;
 
         info!(?intersection, "found intersection");
 
         let worker = Self {
             peer_session,
+            peer_idx,
             quit_on_tip: stage.quit_on_tip,
+            parent: point_hash(&intersection),
         };
 
         Ok(worker)
@@ -148,15 +219,23 @@ impl gasket::framework::Worker<Stage> for Worker {
                         let blocks = self
                             .peer_session
                             .blockfetch()
-                            .fetch_range((start, end))
-                            .await
-                            .or_restart()?;
+                            .fetch_range((start.clone(), end.clone()))
+                            .await;
+
+                        let blocks = stage.or_failover(self.peer_idx, blocks)?;
 
                         info!(len = blocks.len(), "block batch pulled from peer");
 
+                        let verified = verify_bodies(&blocks, &start, &end, self.parent)
+                            .inspect_err(|err| warn!(%err, "peer sent unexpected blocks"));
+
+                        self.parent = stage.or_failover(self.peer_idx, verified)?.into();
+
                         stage.flush_blocks(blocks).await?;
                     }
                     PullBatch::OutOfScopeRollback(point) => {
+                        self.parent = point_hash(&point);
+
                         stage.flush_rollback(point).await?;
                     }
                     PullBatch::Empty => (),
@@ -165,12 +244,20 @@ impl gasket::framework::Worker<Stage> for Worker {
             WorkUnit::Await => {
                 info!("reached tip, waiting for new block");
 
-                let next = self
-                    .peer_session
-                    .chainsync()
-                    .recv_while_must_reply()
-                    .await
-                    .or_restart()?;
+                let next = self.peer_session.chainsync().recv_while_must_reply();
+
+                let next = match stage.peer_timeout {
+                    Some(timeout) => {
+                        let next = tokio::time::timeout(timeout, next)
+                            .await
+                            .inspect_err(|_| warn!(?timeout, "peer went silent"));
+
+                        stage.or_failover(self.peer_idx, next)?
+                    }
+                    None => next.await,
+                };
+
+                let next = stage.or_failover(self.peer_idx, next)?;
 
                 match next {
                     NextResponse::RollForward(header, tip) => {
@@ -182,16 +269,24 @@ impl gasket::framework::Worker<Stage> for Worker {
                         let block = self
                             .peer_session
                             .blockfetch()
-                            .fetch_single(point)
-                            .await
-                            .or_restart()?;
+                            .fetch_single(point.clone())
+                            .await;
+
+                        let blocks = vec![stage.or_failover(self.peer_idx, block)?];
+
+                        let verified = verify_bodies(&blocks, &point, &point, self.parent)
+                            .inspect_err(|err| warn!(%err, "peer sent unexpected block"));
+
+                        self.parent = stage.or_failover(self.peer_idx, verified)?.into();
 
-                        stage.flush_blocks(vec![block]).await?;
+                        stage.flush_blocks(blocks).await?;
                         stage.track_tip(&tip);
                     }
                     NextResponse::RollBackward(point, tip) => {
                         info!(?point, "rollback sent by upstream peer");
 
+                        self.parent = point_hash(&point);
+
                         stage.flush_rollback(point).await?;
                         stage.track_tip(&tip);
                     }
@@ -207,7 +302,9 @@ impl gasket::framework::Worker<Stage> for Worker {
 #[derive(Stage)]
 #[stage(name = "pull", unit = "WorkUnit", worker = "Worker")]
 pub struct Stage {
-    peer_address: String,
+    peers: Vec<String>,
+    peer_timeout: Option<Duration>,
+    current_peer: AtomicUsize,
     network_magic: u64,
     block_fetch_batch_size: usize,
     wal: WalStore,
@@ -220,18 +317,27 @@ pub struct Stage {
 
     #[metric]
     chain_tip: gasket::metrics::Gauge,
+
+    #[metric]
+    peer_failures: gasket::metrics::Counter,
+
+    #[metric]
+    active_peer: gasket::metrics::Gauge,
 }
 
 impl Stage {
     pub fn new(
-        peer_address: String,
+        peers: Vec<String>,
+        peer_timeout: Option<Duration>,
         network_magic: u64,
         block_fetch_batch_size: usize,
         wal: WalStore,
         quit_on_tip: bool,
     ) -> Self {
         Self {
-            peer_address,
+            peers,
+            peer_timeout,
+            current_peer: AtomicUsize::new(0),
             network_magic,
             wal,
             quit_on_tip,
@@ -239,7 +345,86 @@ impl Stage {
             downstream: Default::default(),
             block_count: Default::default(),
             chain_tip: Default::default(),
+            peer_failures: Default::default(),
+            active_peer: Default::default(),
+        }
+    }
+
+    /// Connects to the first reachable peer, starting from the current one
+    ///
+    /// Peers are tried in round-robin order. If none of them can be reached,
+    /// the bootstrap is retried according to the retry policy, which provides
+    /// the backoff between rounds.
+    async fn connect_any_peer(&self) -> Result<(usize, PeerClient), WorkerError> {
+        for idx in self.peer_rotation() {
+            let address = &self.peers[idx];
+
+            debug!(address, "connecting to peer");
+
+            match PeerClient::connect(address, self.network_magic).await {
+                Ok(session) => {
+                    info!(address, magic = self.network_magic, "connected to peer");
+                    self.active_peer.set(idx as i64);
+                    return Ok((idx, session));
+                }
+                Err(err) => {
+                    warn!(address, %err, "couldn't connect to peer");
+                    self.peer_failures.inc(1);
+                }
+            }
         }
+
+        Err(WorkerError::Retry)
+    }
+
+    /// Indexes of the peers in the order they should be tried, starting from
+    /// the current one
+    fn peer_rotation(&self) -> impl Iterator<Item = usize> + '_ {
+        let current = self.current_peer.load(Ordering::Relaxed);
+
+        (0..self.peers.len()).map(move |offset| (current + offset) % self.peers.len())
+    }
+
+    fn peer_failed(&self, idx: usize) {
+        self.peer_failures.inc(1);
+
+        if self.peers.len() > 1 {
+            let next = (idx + 1) % self.peers.len();
+            self.current_peer.store(next, Ordering::Relaxed);
+            warn!(next = self.peers[next], "failing over to next peer");
+        }
+    }
+
+    /// Restarts the worker on errors caused by the peer (connection or protocol
+    /// errors, or unexpected data), moving on to the next peer so that the new
+    /// session doesn't hit the same failing one
+    ///
+    /// Errors that aren't the fault of the peer (eg: storage or decoding of our
+    /// own data) shouldn't go through here, they don't warrant a failover.
+    fn or_failover<T, E: Display>(
+        &self,
+        idx: usize,
+        result: Result<T, E>,
+    ) -> Result<T, WorkerError> {
+        result.inspect_err(|_| self.peer_failed(idx)).or_restart()
+    }
+
+    /// Takes the point found by the intersect request of the bootstrap
+    ///
+    /// Failing to find the intersection is treated as a peer failure, but the
+    /// bootstrap is retried instead of restarted so that the retry policy
+    /// applies while moving through the peers.
+    fn resolve_intersection<E: Display>(
+        &self,
+        idx: usize,
+        found: Result<(Option<Point>, Tip), E>,
+    ) -> Result<Point, WorkerError> {
+        let (point, _) = self
+            .or_failover(idx, found)
+            .map_err(|_| WorkerError::Retry)?;
+
+        self.or_failover(idx, point.ok_or("couldn't find intersect"))
+            .map_err(|_| WorkerError::Retry)
     }
 
     async fn flush_blocks(&mut self, blocks: Vec<BlockBody>) -> Result<(), WorkerError> {
@@ -284,3 +469,149 @@ impl Stage {
         self.chain_tip.set(tip.0.slot_or_default() as i64);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wal::testing;
+
+    fn block_point(block: &RawBlock) -> Point {
+        Point::Specific(block.slot, block.hash.to_vec())
+    }
+
+    #[test]
+    fn test_matching_bodies() {
+        let block = testing::valid_dummy_block();
+        let point = block_point(&block);
+
+        let last = verify_bodies(&[block.body.clone()], &point, &point, None).unwrap();
+        assert_eq!(last, block.hash);
+    }
+
+    #[test]
+    fn test_body_not_matching_header() {
+        let block = testing::valid_dummy_block();
+
+        // the header announced by chainsync doesn't belong to the body we got
+        let point = Point::Specific(block.slot, testing::slot_to_hash(1).to_vec());
+
+        let err = verify_bodies(&[block.body], &point, &point, None).unwrap_err();
+        assert!(matches!(err, BodyMismatch::UnexpectedHash { .. }));
+    }
+
+    #[test]
+    fn test_body_not_following_parent() {
+        let block = testing::valid_dummy_block();
+        let point = block_point(&block);
+
+        let parent = MultiEraBlock::decode(&block.body)
+            .unwrap()
+            .header()
+            .previous_hash();
+
+        assert!(verify_bodies(&[block.body.clone()], &point, &point, parent).is_ok());
+
+        let err = verify_bodies(
+            &[block.body],
+            &point,
+            &point,
+            Some(testing::slot_to_hash(1)),
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, BodyMismatch::BrokenChain { .. }));
+    }
+
+    fn stage_with_peers(count: usize) -> Stage {
+        let peers = (0..count).map(|x| format!("peer-{x}:3001")).collect();
+        let wal = WalStore::memory(None).unwrap();
+
+        Stage::new(peers, None, 0, 10, wal, false)
+    }
+
+    fn current_peer(stage: &Stage) -> usize {
+        stage.current_peer.load(Ordering::Relaxed)
+    }
+
+    #[test]
+    fn test_failover_rotation() {
+        let stage = stage_with_peers(3);
+
+        // the primary peer is tried first, then the fallbacks in order
+        assert_eq!(stage.peer_rotation().collect_vec(), vec![0, 1, 2]);
+
+        let failed: Result<(), _> = Err("connection reset");
+
+        assert!(matches!(
+            stage.or_failover(0, failed),
+            Err(WorkerError::Restart)
+        ));
+        assert_eq!(current_peer(&stage), 1);
+        assert_eq!(stage.peer_rotation().collect_vec(), vec![1, 2, 0]);
+
+        let _ = stage.or_failover(1, failed);
+        assert_eq!(current_peer(&stage), 2);
+
+        // after the last fallback, we're back to the primary peer
+        let _ = stage.or_failover(2, failed);
+        assert_eq!(current_peer(&stage), 0);
+        assert_eq!(stage.peer_rotation().collect_vec(), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_successful_requests_keep_peer() {
+        let stage = stage_with_peers(2);
+
+        let ok: Result<u32, &str> = Ok(1);
+        assert!(matches!(stage.or_failover(0, ok), Ok(1)));
+        assert_eq!(current_peer(&stage), 0);
+    }
+
+    #[test]
+    fn test_bootstrap_intersect_failover() {
+        let stage = stage_with_peers(3);
+        let tip = Tip(Point::Origin, 0);
+
+        // the peer doesn't know any of our candidates
+        let found: Result<_, &str> = Ok((None, tip.clone()));
+        let result = stage.resolve_intersection(0, found);
+
+        assert!(matches!(result, Err(WorkerError::Retry)));
+        assert_eq!(current_peer(&stage), 1);
+
+        // the intersect request itself fails
+        let found: Result<(Option<Point>, Tip), _> = Err("connection reset");
+        let result = stage.resolve_intersection(1, found);
+
+        assert!(matches!(result, Err(WorkerError::Retry)));
+        assert_eq!(current_peer(&stage), 2);
+
+        let point = Point::Specific(10, testing::slot_to_hash(10).to_vec());
+        let found: Result<_, &str> = Ok((Some(point.clone()), tip));
+        let result = stage.resolve_intersection(2, found);
+
+        assert_eq!(result.ok(), Some(point));
+        assert_eq!(current_peer(&stage), 2);
+    }
+
+    #[tokio::test]
+    async fn test_no_failover_on_local_errors() {
+        let mut stage = stage_with_peers(2);
+
+        // undecodable data is only a peer failure when it comes from the peer, once
+        // it's past verification it's our own problem
+        let result = stage.flush_blocks(vec![vec![0xff, 0x00]]).await;
+
+        assert!(matches!(result, Err(WorkerError::Panic)));
+        assert_eq!(current_peer(&stage), 0);
+    }
+
+    #[test]
+    fn test_empty_bodies() {
+        let block = testing::valid_dummy_block();
+        let point = block_point(&block);
+
+        let err = verify_bodies(&[], &point, &point, None).unwrap_err();
+        assert!(matches!(err, BodyMismatch::Empty));
+    }
+}
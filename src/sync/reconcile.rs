@@ -0,0 +1,223 @@
+use pallas::ledger::traverse::MultiEraBlock;
+use thiserror::Error;
+use tracing::{debug, info, warn};
+
+use crate::ledger::pparams::Genesis;
+use crate::state::{LedgerError, LedgerStore};
+use crate::wal::{self, LogValue, WalError, WalReader as _};
+
+#[derive(Debug, Error)]
+pub enum ReconcileError {
+    #[error("wal error: {0}")]
+    Wal(#[from] WalError),
+
+    #[error("ledger error: {0}")]
+    Ledger(#[from] LedgerError),
+
+    #[error("block decoding error: {0}")]
+    Decoding(#[from] pallas::ledger::traverse::Error),
+
+    #[error("wal tip {wal:?} is behind ledger cursor {ledger:?}, the wal is probably corrupted; restore it from a snapshot or run `dolos doctor rebuild-ledger --yes` to re-create the ledger from the wal")]
+    WalBehindLedger {
+        wal: Option<wal::ChainPoint>,
+        ledger: wal::ChainPoint,
+    },
+
+    #[error("ledger cursor {0:?} is not part of the wal, the wal was pruned or diverged; run `dolos doctor rebuild-ledger --yes` to re-create the ledger from the wal")]
+    LedgerNotInWal(wal::ChainPoint),
+}
+
+/// Summary of the changes applied to bring the ledger up to the WAL tip
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Reconciliation {
+    pub applied: usize,
+    pub undone: usize,
+    pub tip: Option<wal::ChainPoint>,
+}
+
+fn slot_of(point: &wal::ChainPoint) -> Option<u64> {
+    match point {
+        wal::ChainPoint::Origin => None,
+        wal::ChainPoint::Specific(slot, _) => Some(*slot),
+    }
+}
+
+/// Brings the ledger up to date with the WAL before any other task starts
+///
+/// If the process stopped between a WAL write and the corresponding ledger
+/// update, the missing WAL entries are replayed through the same code used by
+/// the apply stage. A ledger that is ahead of the WAL can't be fixed
+/// automatically, in which case an error with guidance is returned.
+pub fn reconcile_on_startup(
+    wal: &wal::redb::WalStore,
+    ledger: &LedgerStore,
+    genesis: &Genesis,
+) -> Result<Reconciliation, ReconcileError> {
+    let Some((_, tip)) = wal.find_tip()? else {
+        debug!("empty wal, nothing to reconcile");
+        return Ok(Reconciliation::default());
+    };
+
+    let cursor = match ledger.cursor()? {
+        Some(crate::ledger::ChainPoint(slot, hash)) => wal::ChainPoint::Specific(slot, hash),
+        None => wal::ChainPoint::Origin,
+    };
+
+    let Some(seq) = wal.locate_point(&cursor)? else {
+        let behind = match (slot_of(&tip), slot_of(&cursor)) {
+            (_, None) => false,
+            (None, Some(_)) => true,
+            (Some(tip), Some(cursor)) => tip < cursor,
+        };
+
+        return match behind {
+            true => Err(ReconcileError::WalBehindLedger {
+                wal: Some(tip),
+                ledger: cursor,
+            }),
+            false => Err(ReconcileError::LedgerNotInWal(cursor)),
+        };
+    };
+
+    // the origin mark is never skipped by the apply stage, an empty ledger needs
+    // the genesis utxos before any block can be applied.
+    if ledger.is_empty()? {
+        info!("ledger is empty, applying origin");
+        crate::state::apply_origin(ledger, genesis)?;
+    }
+
+    let mut out = Reconciliation::default();
+
+    for (_, log) in wal.crawl_from(Some(seq))?.skip(1) {
+        match log {
+            LogValue::Apply(block) => {
+                let block = MultiEraBlock::decode(&block.body)?;
+                crate::state::apply_block_batch([&block], ledger, genesis)?;
+                out.applied += 1;
+            }
+            LogValue::Undo(block) => {
+                let block = MultiEraBlock::decode(&block.body)?;
+                crate::state::undo_block(&block, ledger)?;
+                out.undone += 1;
+            }
+            LogValue::Mark(wal::ChainPoint::Origin) => {
+                crate::state::apply_origin(ledger, genesis)?;
+            }
+            LogValue::Mark(..) => (),
+        }
+    }
+
+    if out.applied > 0 || out.undone > 0 {
+        warn!(
+            applied = out.applied,
+            undone = out.undone,
+            "ledger was behind wal, caught up"
+        );
+    } else {
+        info!("ledger is in sync with wal");
+    }
+
+    out.tip = Some(tip);
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::wal::{testing, WalWriter as _};
+
+    fn load_json<T>(path: &str) -> T
+    where
+        T: serde::de::DeserializeOwned,
+    {
+        let file = std::fs::File::open(path).unwrap();
+        serde_json::from_reader(file).unwrap()
+    }
+
+    fn test_genesis() -> Arc<Genesis> {
+        let test_data = "src/ledger/pparams/test_data/mainnet/genesis";
+
+        Arc::new(Genesis {
+            byron: load_json(&format!("{test_data}/byron_genesis.json")),
+            shelley: load_json(&format!("{test_data}/shelley_genesis.json")),
+            alonzo: load_json(&format!("{test_data}/alonzo_genesis.json")),
+            conway: load_json(&format!("{test_data}/conway_genesis.json")),
+            force_protocol: None,
+            cost_model_tolerance: Default::default(),
+        })
+    }
+
+    fn wal_with_block() -> (wal::redb::WalStore, wal::RawBlock) {
+        let mut wal = testing::empty_db();
+        let block = testing::valid_dummy_block();
+
+        wal.roll_forward(std::iter::once(block.clone())).unwrap();
+
+        (wal, block)
+    }
+
+    fn ledger_point(ledger: &LedgerStore) -> Option<wal::ChainPoint> {
+        ledger
+            .cursor()
+            .unwrap()
+            .map(|crate::ledger::ChainPoint(s, h)| wal::ChainPoint::Specific(s, h))
+    }
+
+    #[test]
+    fn test_ledger_behind_wal() {
+        let genesis = test_genesis();
+        let (wal, block) = wal_with_block();
+        let ledger: LedgerStore = crate::state::redb::LedgerStore::in_memory_v2()
+            .unwrap()
+            .into();
+
+        let out = reconcile_on_startup(&wal, &ledger, &genesis).unwrap();
+
+        assert_eq!(out.applied, 1);
+        assert_eq!(out.undone, 0);
+        assert_eq!(ledger_point(&ledger), Some(wal::ChainPoint::from(&block)));
+
+        // running again is a no-op
+        let out = reconcile_on_startup(&wal, &ledger, &genesis).unwrap();
+        assert_eq!(out.applied, 0);
+    }
+
+    #[test]
+    fn test_ledger_behind_wal_with_rollback() {
+        let genesis = test_genesis();
+        let (mut wal, block) = wal_with_block();
+        let ledger: LedgerStore = crate::state::redb::LedgerStore::in_memory_v2()
+            .unwrap()
+            .into();
+
+        reconcile_on_startup(&wal, &ledger, &genesis).unwrap();
+
+        wal.roll_back(&wal::ChainPoint::Origin).unwrap();
+
+        let out = reconcile_on_startup(&wal, &ledger, &genesis).unwrap();
+
+        assert_eq!(out.undone, 1);
+        assert!(ledger_point(&ledger) != Some(wal::ChainPoint::from(&block)));
+    }
+
+    #[test]
+    fn test_wal_behind_ledger() {
+        let genesis = test_genesis();
+        let (wal, _) = wal_with_block();
+        let ledger: LedgerStore = crate::state::redb::LedgerStore::in_memory_v2()
+            .unwrap()
+            .into();
+
+        reconcile_on_startup(&wal, &ledger, &genesis).unwrap();
+
+        // a wal that only has the origin is behind a ledger that applied a block
+        let wal = testing::empty_db();
+
+        let err = reconcile_on_startup(&wal, &ledger, &genesis).unwrap_err();
+
+        assert!(matches!(err, ReconcileError::WalBehindLedger { .. }));
+    }
+}
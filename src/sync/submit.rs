@@ -37,7 +37,7 @@ impl Worker {
         stage: &mut Stage,
         request: usize,
     ) -> Result<WorkSchedule<Request<EraTxId>>, WorkerError> {
-        let available = stage.mempool.pending_total();
+        let available = stage.mempool.pending_total(&stage.peer_address);
 
         if available > 0 {
             debug!(request, available, "found enough txs to fulfill request");
@@ -84,6 +84,10 @@ impl gasket::framework::Worker<Stage> for Worker {
     async fn bootstrap(stage: &Stage) -> Result<Self, WorkerError> {
         debug!("connecting to peer");
 
+        // any tx that was inflight on a previous session never got acknowledged, we
+        // return them to the queue so that they are offered again.
+        stage.mempool.restore_inflight(&stage.peer_address);
+
         let mut peer_session = PeerClient::connect(&stage.peer_address, stage.network_magic)
             .await
             .or_retry()?;
@@ -129,12 +133,12 @@ impl gasket::framework::Worker<Stage> for Worker {
 
                 info!(req, ack, "blocking tx ids request");
 
-                stage.mempool.acknowledge(ack);
+                stage.mempool.acknowledge(&stage.peer_address, ack);
 
-                let available = stage.mempool.pending_total();
+                let available = stage.mempool.pending_total(&stage.peer_address);
 
                 if available > 0 {
-                    let txs = stage.mempool.request(req);
+                    let txs = stage.mempool.request(&stage.peer_address, req);
                     self.propagate_txs(txs).await?;
                 } else {
                     debug!(req, available, "not enough txs to fulfill request");
@@ -144,9 +148,11 @@ impl gasket::framework::Worker<Stage> for Worker {
             Request::TxIdsNonBlocking(ack, req) => {
                 info!(req, ack, "non-blocking tx ids request");
 
-                stage.mempool.acknowledge(*ack as usize);
+                stage
+                    .mempool
+                    .acknowledge(&stage.peer_address, *ack as usize);
 
-                let txs = stage.mempool.request(*req as usize);
+                let txs = stage.mempool.request(&stage.peer_address, *req as usize);
                 self.propagate_txs(txs).await?;
             }
             Request::Txs(ids) => {
@@ -156,7 +162,11 @@ impl gasket::framework::Worker<Stage> for Worker {
                     .iter()
                     // we omit any missing tx, we assume that this would be considered a protocol
                     // violation and rejected by the upstream.
-                    .filter_map(|x| stage.mempool.find_inflight(&Hash::from(x.1.as_slice())))
+                    .filter_map(|x| {
+                        stage
+                            .mempool
+                            .find_inflight(&stage.peer_address, &Hash::from(x.1.as_slice()))
+                    })
                     .map(|x| EraTxBody(x.era, x.bytes.clone()))
                     .collect_vec();
 
@@ -184,6 +194,8 @@ pub struct Stage {
 
 impl Stage {
     pub fn new(peer_address: String, network_magic: u64, mempool: Mempool) -> Self {
+        mempool.register_peer(&peer_address);
+
         Self {
             peer_address,
             network_magic,
@@ -0,0 +1,329 @@
+use pallas::ledger::traverse::MultiEraBlock;
+use std::collections::{HashMap, VecDeque};
+
+use super::*;
+
+/// An inconsistency found while verifying the entries of the WAL
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntegrityIssue {
+    /// The block body can't be decoded, usually a sign of a truncated record
+    DecodeError(ChainPoint),
+
+    /// The hash of the decoded block doesn't match the hash stored in the WAL
+    HashMismatch {
+        point: ChainPoint,
+        computed: BlockHash,
+    },
+
+    /// The slot of the decoded block doesn't match the slot stored in the WAL
+    SlotMismatch {
+        point: ChainPoint,
+        computed: BlockSlot,
+    },
+
+    /// The block doesn't point to the previous block of the chain
+    BrokenChain {
+        point: ChainPoint,
+        expected: BlockHash,
+        found: Option<BlockHash>,
+    },
+
+    /// The block number is lower than the number of the previous block
+    NonMonotonicNumber {
+        point: ChainPoint,
+        previous: u64,
+        found: u64,
+    },
+}
+
+impl IntegrityIssue {
+    pub fn point(&self) -> &ChainPoint {
+        match self {
+            IntegrityIssue::DecodeError(point) => point,
+            IntegrityIssue::HashMismatch { point, .. } => point,
+            IntegrityIssue::SlotMismatch { point, .. } => point,
+            IntegrityIssue::BrokenChain { point, .. } => point,
+            IntegrityIssue::NonMonotonicNumber { point, .. } => point,
+        }
+    }
+}
+
+/// Max number of applied blocks tracked to resolve rollbacks, matches the
+/// security parameter of the Cardano networks
+pub const DEFAULT_ROLLBACK_WINDOW: usize = 2160;
+
+/// Verifies a sequence of WAL entries, one at a time
+///
+/// Each applied block is decoded and its hash and slot are recomputed and
+/// compared against the values stored in the WAL. Consecutive blocks are also
+/// checked for prev-hash chaining and block number monotonicity. Undo and mark
+/// entries are tracked so that rollbacks don't show up as broken chains.
+///
+/// Only the last applied blocks (up to the rollback window) are kept to resolve
+/// undo and mark entries, so memory doesn't grow with the size of the WAL.
+pub struct IntegrityCheck {
+    window: usize,
+    next_idx: u64,
+    applied: VecDeque<(u64, BlockHash, Option<u64>)>,
+    by_hash: HashMap<BlockHash, u64>,
+}
+
+impl Default for IntegrityCheck {
+    fn default() -> Self {
+        Self::new(DEFAULT_ROLLBACK_WINDOW)
+    }
+}
+
+impl IntegrityCheck {
+    pub fn new(window: usize) -> Self {
+        Self {
+            window: window.max(1),
+            next_idx: 0,
+            applied: VecDeque::new(),
+            by_hash: HashMap::new(),
+        }
+    }
+
+    fn push(&mut self, hash: BlockHash, number: Option<u64>) {
+        let idx = self.next_idx;
+        self.next_idx += 1;
+
+        self.applied.push_back((idx, hash, number));
+        self.by_hash.insert(hash, idx);
+
+        while self.applied.len() > self.window {
+            if let Some((idx, hash, _)) = self.applied.pop_front() {
+                self.forget(idx, &hash);
+            }
+        }
+    }
+
+    fn forget(&mut self, idx: u64, hash: &BlockHash) {
+        // a newer entry might have been applied with the same hash
+        if self.by_hash.get(hash) == Some(&idx) {
+            self.by_hash.remove(hash);
+        }
+    }
+
+    /// Drops every tracked block applied after the given index
+    fn truncate_after(&mut self, idx: u64) {
+        while self.applied.back().is_some_and(|(x, ..)| *x > idx) {
+            if let Some((idx, hash, _)) = self.applied.pop_back() {
+                self.forget(idx, &hash);
+            }
+        }
+    }
+
+    fn clear(&mut self) {
+        self.applied.clear();
+        self.by_hash.clear();
+    }
+
+    /// Checks a block against the tip, returning the issues found and the
+    /// block number if it could be decoded
+    fn check_block(&self, block: &RawBlock) -> (Vec<IntegrityIssue>, Option<u64>) {
+        let point = ChainPoint::from(block);
+
+        let decoded = match MultiEraBlock::decode(&block.body) {
+            Ok(x) => x,
+            Err(_) => return (vec![IntegrityIssue::DecodeError(point)], None),
+        };
+
+        let mut issues = vec![];
+
+        if decoded.hash() != block.hash {
+            issues.push(IntegrityIssue::HashMismatch {
+                point: point.clone(),
+                computed: decoded.hash(),
+            });
+        }
+
+        if decoded.slot() != block.slot {
+            issues.push(IntegrityIssue::SlotMismatch {
+                point: point.clone(),
+                computed: decoded.slot(),
+            });
+        }
+
+        if let Some((_, prev_hash, prev_number)) = self.applied.back() {
+            let found = decoded.header().previous_hash();
+
+            if found != Some(*prev_hash) {
+                issues.push(IntegrityIssue::BrokenChain {
+                    point: point.clone(),
+                    expected: *prev_hash,
+                    found,
+                });
+            }
+
+            // Byron EBBs share the number of the block that precedes them, so we only
+            // flag numbers that go backwards.
+            if let Some(previous) = prev_number {
+                if decoded.number() < *previous {
+                    issues.push(IntegrityIssue::NonMonotonicNumber {
+                        point,
+                        previous: *previous,
+                        found: decoded.number(),
+                    });
+                }
+            }
+        }
+
+        (issues, Some(decoded.number()))
+    }
+
+    /// Checks the next entry of the WAL, returning any issue found
+    pub fn check(&mut self, log: &LogValue) -> Vec<IntegrityIssue> {
+        match log {
+            LogValue::Apply(block) => {
+                let (issues, number) = self.check_block(block);
+
+                self.push(block.hash, number);
+
+                issues
+            }
+            LogValue::Undo(block) => {
+                if let Some(idx) = self.by_hash.get(&block.hash).copied() {
+                    self.truncate_after(idx);
+
+                    if let Some((idx, hash, _)) = self.applied.pop_back() {
+                        self.forget(idx, &hash);
+                    }
+                }
+
+                vec![]
+            }
+            LogValue::Mark(ChainPoint::Specific(_, hash)) => {
+                match self.by_hash.get(hash).copied() {
+                    Some(idx) => self.truncate_after(idx),
+                    // the mark points to a block we haven't seen (or that is past the window),
+                    // we only know its hash
+                    None => {
+                        self.clear();
+                        self.push(*hash, None);
+                    }
+                }
+
+                vec![]
+            }
+            LogValue::Mark(ChainPoint::Origin) => {
+                self.clear();
+                vec![]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_valid_block() {
+        let mut check = IntegrityCheck::default();
+
+        let issues = check.check(&LogValue::Apply(testing::valid_dummy_block()));
+        assert!(issues.is_empty());
+    }
+
+    #[test]
+    fn test_hash_mismatch() {
+        let mut check = IntegrityCheck::default();
+
+        let mut block = testing::valid_dummy_block();
+        let computed = block.hash;
+        block.hash = testing::slot_to_hash(99);
+
+        let issues = check.check(&LogValue::Apply(block.clone()));
+
+        assert_eq!(
+            issues,
+            vec![IntegrityIssue::HashMismatch {
+                point: ChainPoint::from(&block),
+                computed
+            }]
+        );
+    }
+
+    #[test]
+    fn test_truncated_body() {
+        let mut check = IntegrityCheck::default();
+
+        let mut block = testing::valid_dummy_block();
+        block.body.truncate(block.body.len() / 2);
+
+        let issues = check.check(&LogValue::Apply(block.clone()));
+
+        assert_eq!(
+            issues,
+            vec![IntegrityIssue::DecodeError(ChainPoint::from(&block))]
+        );
+    }
+
+    #[test]
+    fn test_broken_chain() {
+        let mut check = IntegrityCheck::default();
+
+        let block = testing::valid_dummy_block();
+
+        assert!(check.check(&LogValue::Apply(block.clone())).is_empty());
+
+        // the same block can't follow itself
+        let issues = check.check(&LogValue::Apply(block.clone()));
+
+        assert!(matches!(
+            issues.as_slice(),
+            [IntegrityIssue::BrokenChain { expected, .. }] if *expected == block.hash
+        ));
+    }
+
+    #[test]
+    fn test_rollback_resets_chain() {
+        let mut check = IntegrityCheck::default();
+
+        let block = testing::valid_dummy_block();
+
+        let prev_hash = MultiEraBlock::decode(&block.body)
+            .unwrap()
+            .header()
+            .previous_hash()
+            .unwrap();
+
+        assert!(check.check(&LogValue::Apply(block.clone())).is_empty());
+        assert!(check.check(&LogValue::Undo(block.clone())).is_empty());
+
+        // after rolling back to the parent of the block, applying it again is valid
+        let mark = LogValue::Mark(ChainPoint::Specific(block.slot, prev_hash));
+        assert!(check.check(&mark).is_empty());
+        assert!(check.check(&LogValue::Apply(block)).is_empty());
+    }
+
+    #[test]
+    fn test_window_is_bounded() {
+        let mut check = IntegrityCheck::new(3);
+
+        for slot in 0..10 {
+            check.check(&LogValue::Apply(testing::dummy_block_from_slot(slot)));
+        }
+
+        assert_eq!(check.applied.len(), 3);
+        assert_eq!(check.by_hash.len(), 3);
+
+        // undoing a block past the window leaves the tracked blocks untouched
+        check.check(&LogValue::Undo(testing::dummy_block_from_slot(2)));
+        assert_eq!(check.applied.len(), 3);
+
+        // undoing a block within the window drops it and everything after it
+        check.check(&LogValue::Undo(testing::dummy_block_from_slot(8)));
+        assert_eq!(check.applied.len(), 1);
+        assert_eq!(check.applied.back().unwrap().1, testing::slot_to_hash(7));
+
+        // marks past the window restart the chain from the marked hash
+        let mark = ChainPoint::Specific(1, testing::slot_to_hash(1));
+        check.check(&LogValue::Mark(mark));
+
+        assert_eq!(check.applied.len(), 1);
+        assert_eq!(check.by_hash.len(), 1);
+        assert_eq!(check.applied.back().unwrap().1, testing::slot_to_hash(1));
+    }
+}
@@ -3,6 +3,7 @@ use pallas::network::miniprotocols::Point as PallasPoint;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+mod integrity;
 mod reader;
 mod stream;
 mod writer;
@@ -37,6 +38,30 @@ impl PartialEq for ChainPoint {
     }
 }
 
+impl std::fmt::Display for ChainPoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainPoint::Origin => write!(f, "origin"),
+            ChainPoint::Specific(slot, hash) => write!(f, "{slot},{hash}"),
+        }
+    }
+}
+
+/// Parses a chain point, either `origin` or `{slot},{block hash hex}`
+impl std::str::FromStr for ChainPoint {
+    type Err = crate::ledger::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("origin") {
+            return Ok(ChainPoint::Origin);
+        }
+
+        let crate::ledger::ChainPoint(slot, hash) = s.parse()?;
+
+        Ok(ChainPoint::Specific(slot, hash))
+    }
+}
+
 impl From<PallasPoint> for ChainPoint {
     fn from(value: PallasPoint) -> Self {
         match value {
@@ -100,6 +125,25 @@ impl PartialEq for LogValue {
 
 pub type LogEntry = (LogSeq, LogValue);
 
+/// A rollback applied to the WAL, kept for chain quality monitoring
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RollbackRecord {
+    /// Unix timestamp (in seconds) of when the rollback was applied
+    pub timestamp: u64,
+
+    /// The tip of the WAL before the rollback
+    pub from: ChainPoint,
+
+    /// The point the WAL was rolled back to
+    pub to: ChainPoint,
+
+    /// Number of blocks that were undone
+    pub depth_blocks: u64,
+
+    /// Number of slots between the previous tip and the rollback point
+    pub depth_slots: u64,
+}
+
 #[derive(Debug, Error)]
 pub enum WalError {
     #[error("wal is not empty")]
@@ -115,6 +159,7 @@ pub enum WalError {
     IO(#[source] Box<dyn std::error::Error + Send + Sync>),
 }
 
+pub use integrity::{IntegrityCheck, IntegrityIssue};
 pub use reader::{ReadUtils, WalReader};
 pub use stream::WalStream;
 pub use writer::WalWriter;
@@ -153,4 +198,21 @@ mod tests {
             ChainPoint::Specific(50, slot_to_hash(50)),
         );
     }
+
+    #[test]
+    fn chainpoint_text_roundtrip() {
+        let points = [
+            ChainPoint::Origin,
+            ChainPoint::Specific(0, slot_to_hash(0)),
+            ChainPoint::Specific(u64::MAX, slot_to_hash(50)),
+        ];
+
+        for point in points {
+            let text = point.to_string();
+            assert_eq!(text.parse::<ChainPoint>().unwrap(), point);
+        }
+
+        assert_eq!("origin".to_string(), ChainPoint::Origin.to_string());
+        assert_eq!("ORIGIN".parse::<ChainPoint>().unwrap(), ChainPoint::Origin);
+    }
 }
@@ -4,8 +4,11 @@ use redb::{Range, ReadableTable, TableDefinition};
 use std::{path::Path, sync::Arc};
 use tracing::{debug, info, trace, warn};
 
+use crate::model::DurabilityMode;
+
 use super::{
-    BlockSlot, ChainPoint, LogEntry, LogSeq, LogValue, RawBlock, WalError, WalReader, WalWriter,
+    BlockSlot, ChainPoint, LogEntry, LogSeq, LogValue, RawBlock, RollbackRecord, WalError,
+    WalReader, WalWriter,
 };
 
 impl redb::Value for LogValue {
@@ -39,10 +42,45 @@ impl redb::Value for LogValue {
     }
 }
 
+impl redb::Value for RollbackRecord {
+    type SelfType<'a> = Self;
+    type AsBytes<'a>
+        = Vec<u8>
+    where
+        Self: 'a;
+
+    fn fixed_width() -> Option<usize> {
+        None
+    }
+
+    fn from_bytes<'a>(data: &'a [u8]) -> Self
+    where
+        Self: 'a,
+    {
+        bincode::deserialize(data).unwrap()
+    }
+
+    fn as_bytes<'a, 'b: 'a>(value: &'a Self::SelfType<'b>) -> Self::AsBytes<'a>
+    where
+        Self: 'a,
+        Self: 'b,
+    {
+        bincode::serialize(value).unwrap()
+    }
+
+    fn type_name() -> redb::TypeName {
+        redb::TypeName::new("rollbackrecord")
+    }
+}
+
 pub type AugmentedBlockSlot = i128;
 
 const WAL: TableDefinition<LogSeq, LogValue> = TableDefinition::new("wal");
 const POS: TableDefinition<AugmentedBlockSlot, LogSeq> = TableDefinition::new("pos");
+const ROLLBACKS: TableDefinition<u64, RollbackRecord> = TableDefinition::new("rollbacks");
+
+/// Number of rollbacks kept in the log unless configured otherwise
+pub const DEFAULT_MAX_ROLLBACKS: u64 = 1_000;
 
 fn point_to_augmented_slot(point: &ChainPoint) -> AugmentedBlockSlot {
     match point {
@@ -84,12 +122,43 @@ where
 
 const DEFAULT_CACHE_SIZE_MB: usize = 50;
 
+fn point_slot(point: &ChainPoint) -> BlockSlot {
+    match point {
+        ChainPoint::Origin => 0,
+        ChainPoint::Specific(slot, _) => *slot,
+    }
+}
+
+fn unix_timestamp() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+/// Builds the record of a rollback out of the undone blocks (newest first) and
+/// the point the chain was rolled back to. Rollbacks to the current tip don't
+/// undo anything and aren't recorded.
+fn rollback_record(undos: &[RawBlock], to: &ChainPoint) -> Option<RollbackRecord> {
+    let from = ChainPoint::from(undos.first()?);
+
+    Some(RollbackRecord {
+        timestamp: unix_timestamp(),
+        depth_blocks: undos.len() as u64,
+        depth_slots: point_slot(&from).saturating_sub(point_slot(to)),
+        from,
+        to: to.clone(),
+    })
+}
+
 /// Concrete implementation of WalStore using Redb
 #[derive(Clone, Debug)]
 pub struct WalStore {
     db: Arc<redb::Database>,
     max_slots: Option<u64>,
     tip_change: Arc<tokio::sync::Notify>,
+    durability: DurabilityMode,
+    max_rollbacks: Option<u64>,
 }
 
 impl WalStore {
@@ -130,6 +199,8 @@ impl WalStore {
             db: Arc::new(db),
             tip_change: Arc::new(tokio::sync::Notify::new()),
             max_slots,
+            durability: DurabilityMode::default(),
+            max_rollbacks: None,
         };
 
         Ok(out)
@@ -149,6 +220,8 @@ impl WalStore {
             db: Arc::new(inner),
             tip_change: Arc::new(tokio::sync::Notify::new()),
             max_slots,
+            durability: DurabilityMode::default(),
+            max_rollbacks: None,
         };
 
         Ok(out)
@@ -158,6 +231,41 @@ impl WalStore {
         Arc::get_mut(&mut self.db)
     }
 
+    /// Sets the durability used when appending entries according to the given
+    /// mode
+    pub fn set_durability(&mut self, mode: DurabilityMode) {
+        self.durability = mode;
+    }
+
+    /// Sets how many rollbacks are kept in the log before evicting the oldest
+    /// ones, [DEFAULT_MAX_ROLLBACKS] if not set
+    pub fn set_max_rollbacks(&mut self, max: Option<u64>) {
+        self.max_rollbacks = max;
+    }
+
+    /// Returns the logged rollbacks, oldest first
+    pub fn rollback_history(&self) -> Result<Vec<RollbackRecord>, WalError> {
+        let rx = self.db.begin_read()?;
+
+        let table = match rx.open_table(ROLLBACKS) {
+            Ok(x) => x,
+            Err(redb::TableError::TableDoesNotExist(_)) => return Ok(vec![]),
+            Err(x) => return Err(x.into()),
+        };
+
+        let records = table
+            .iter()?
+            .map_ok(|(_, v)| v.value())
+            .collect::<Result<_, _>>()?;
+
+        Ok(records)
+    }
+
+    /// Returns the key count and size of each of the WAL tables
+    pub fn stats(&self) -> Result<Vec<crate::model::TableStats>, WalError> {
+        Ok(crate::model::TableStats::collect_redb(&self.db)?)
+    }
+
     // TODO: see how to expose this method through the official write interface
     // TODO: improve performance, this approach is immensely inefficient
     pub fn remove_range(
@@ -464,6 +572,28 @@ impl WalStore {
 
         Ok(())
     }
+
+    /// Removes all entries from the WAL after the specified point.
+    ///
+    /// The point itself is kept, leaving it as the new tip of the WAL. Unlike
+    /// a rollback, no undo entries are recorded; this is meant for offline
+    /// maintenance where downstream consumers are already aware of the
+    /// discarded entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns `WalError::PointNotFound` if the point doesn't exist in the WAL.
+    pub fn truncate_after(&mut self, point: &ChainPoint) -> Result<(), WalError> {
+        let seq = self.assert_point(point)?;
+
+        debug!(seq, "truncating wal after sequence");
+
+        self.remove_range(Some(seq + 1), None)?;
+
+        self.tip_change.notify_waiters();
+
+        Ok(())
+    }
 }
 
 impl super::WalReader for WalStore {
@@ -511,34 +641,97 @@ impl super::WalReader for WalStore {
     }
 }
 
+impl WalStore {
+    fn write_entries(
+        wx: &redb::WriteTransaction,
+        logs: impl Iterator<Item = LogValue>,
+    ) -> Result<(), WalError> {
+        let mut wal = wx.open_table(WAL)?;
+        let mut pos = wx.open_table(POS)?;
+
+        let mut next_seq = wal.last()?.map(|(x, _)| x.value() + 1).unwrap_or_default();
+
+        for log in logs {
+            // Since we need to track Origin as part of the wal, we turn slots into signed
+            // integers and treat -1 as the reference for Origin. This is not ideal from
+            // disk space perspective, but good enough for this stage.
+            let pos_key = match &log {
+                LogValue::Apply(RawBlock { slot, .. }) => *slot as i128,
+                LogValue::Undo(RawBlock { slot, .. }) => *slot as i128,
+                LogValue::Mark(x) => point_to_augmented_slot(x),
+            };
+
+            pos.insert(pos_key, next_seq)?;
+            wal.insert(next_seq, log)?;
+
+            next_seq += 1;
+        }
+
+        Ok(())
+    }
+
+    fn write_rollback_record(
+        &self,
+        wx: &redb::WriteTransaction,
+        record: RollbackRecord,
+    ) -> Result<(), WalError> {
+        info!(
+            depth_blocks = record.depth_blocks,
+            depth_slots = record.depth_slots,
+            "logging wal rollback"
+        );
+
+        let mut rollbacks = wx.open_table(ROLLBACKS)?;
+
+        let next = rollbacks.last()?.map(|(x, _)| x.value() + 1);
+        rollbacks.insert(next.unwrap_or_default(), record)?;
+
+        let max = self.max_rollbacks.unwrap_or(DEFAULT_MAX_ROLLBACKS);
+
+        while rollbacks.len()? > max {
+            rollbacks.pop_first()?;
+        }
+
+        Ok(())
+    }
+}
+
 impl super::WalWriter for WalStore {
     fn append_entries(
         &mut self,
         logs: impl Iterator<Item = super::LogValue>,
     ) -> Result<(), super::WalError> {
-        let wx = self.db.begin_write()?;
+        let mut wx = self.db.begin_write()?;
+        wx.set_durability(self.durability.wal());
 
-        {
-            let mut wal = wx.open_table(WAL)?;
-            let mut pos = wx.open_table(POS)?;
+        Self::write_entries(&wx, logs)?;
 
-            let mut next_seq = wal.last()?.map(|(x, _)| x.value() + 1).unwrap_or_default();
+        wx.commit()?;
 
-            for log in logs {
-                // Since we need to track Origin as part of the wal, we turn slots into signed
-                // integers and treat -1 as the reference for Origin. This is not ideal from
-                // disk space perspective, but good enough for this stage.
-                let pos_key = match &log {
-                    LogValue::Apply(RawBlock { slot, .. }) => *slot as i128,
-                    LogValue::Undo(RawBlock { slot, .. }) => *slot as i128,
-                    LogValue::Mark(x) => point_to_augmented_slot(x),
-                };
+        self.tip_change.notify_waiters();
 
-                pos.insert(pos_key, next_seq)?;
-                wal.insert(next_seq, log)?;
+        Ok(())
+    }
 
-                next_seq += 1;
-            }
+    fn append_rollback(
+        &mut self,
+        undos: Vec<RawBlock>,
+        mark: ChainPoint,
+    ) -> Result<(), super::WalError> {
+        let mut wx = self.db.begin_write()?;
+        wx.set_durability(self.durability.wal());
+
+        // rollbacks are logged within the same transaction that undoes the
+        // blocks, so that we never record a rollback that didn't happen
+        let record = rollback_record(&undos, &mark);
+
+        let undos = undos.into_iter().map(LogValue::Undo);
+        let mark = std::iter::once(LogValue::Mark(mark));
+
+        Self::write_entries(&wx, undos.chain(mark))?;
+
+        if let Some(record) = record {
+            self.write_rollback_record(&wx, record)?;
         }
 
         wx.commit()?;
@@ -548,3 +741,155 @@ impl super::WalWriter for WalStore {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::super::testing;
+    use super::*;
+
+    #[test]
+    fn test_truncate_after() {
+        let mut db = testing::db_with_dummy_blocks(10);
+
+        let target = ChainPoint::Specific(6, testing::slot_to_hash(6));
+        db.truncate_after(&target).unwrap();
+
+        // ensure tip is the target point
+        let (_, tip) = db.find_tip().unwrap().unwrap();
+        assert_eq!(tip, target);
+
+        // ensure removed blocks can't be located
+        for slot in 7..10 {
+            let point = ChainPoint::Specific(slot, testing::slot_to_hash(slot));
+            assert!(db.locate_point(&point).unwrap().is_none());
+        }
+
+        // ensure the wal can keep rolling forward from the target
+        db.roll_forward(std::iter::once(testing::dummy_block_from_slot(7)))
+            .unwrap();
+
+        let (seq, tip) = db.find_tip().unwrap().unwrap();
+        assert_eq!(tip, ChainPoint::Specific(7, testing::slot_to_hash(7)));
+        assert_eq!(seq, 8);
+    }
+
+    #[test]
+    fn test_stats_track_appends() {
+        let mut db = testing::db_with_dummy_blocks(3);
+
+        let before = db.stats().unwrap();
+        let wal = before.iter().find(|x| x.name == "wal").unwrap();
+        assert_eq!(wal.keys, 4);
+
+        db.roll_forward((3..5).map(testing::dummy_block_from_slot))
+            .unwrap();
+
+        let after = db.stats().unwrap();
+        let wal = after.iter().find(|x| x.name == "wal").unwrap();
+        assert_eq!(wal.keys, 6);
+
+        let pos = after.iter().find(|x| x.name == "pos").unwrap();
+        assert_eq!(pos.keys, 6);
+    }
+
+    #[test]
+    fn test_rollback_history() {
+        let mut db = testing::db_with_dummy_blocks(20);
+
+        // a rollback to the current tip doesn't undo anything
+        let tip = ChainPoint::Specific(19, testing::slot_to_hash(19));
+        db.roll_back(&tip).unwrap();
+        assert!(db.rollback_history().unwrap().is_empty());
+
+        let target = ChainPoint::Specific(15, testing::slot_to_hash(15));
+        db.roll_back(&target).unwrap();
+
+        db.roll_forward((16..18).map(testing::dummy_block_from_slot))
+            .unwrap();
+
+        // nested rollback, deeper than the previous one
+        let target = ChainPoint::Specific(10, testing::slot_to_hash(10));
+        db.roll_back(&target).unwrap();
+
+        let history = db.rollback_history().unwrap();
+        assert_eq!(history.len(), 2);
+
+        assert_eq!(history[0].from, tip);
+        assert_eq!(history[0].depth_blocks, 4);
+        assert_eq!(history[0].depth_slots, 4);
+
+        let from = ChainPoint::Specific(17, testing::slot_to_hash(17));
+        assert_eq!(history[1].from, from);
+        assert_eq!(history[1].to, target);
+        assert_eq!(history[1].depth_blocks, 7);
+        assert_eq!(history[1].depth_slots, 7);
+
+        // blocks undone by the first rollback aren't undone again
+        let undone: Vec<_> = db
+            .crawl_from(None)
+            .unwrap()
+            .filter_map(|(_, x)| match x {
+                LogValue::Undo(block) => Some(block.slot),
+                _ => None,
+            })
+            .collect();
+
+        assert_eq!(undone, vec![19, 18, 17, 16, 17, 16, 15, 14, 13, 12, 11]);
+    }
+
+    #[test]
+    fn test_copied_entries_dont_log_rollbacks() {
+        let mut source = testing::db_with_dummy_blocks(10);
+
+        for slot in [8, 6] {
+            let target = ChainPoint::Specific(slot, testing::slot_to_hash(slot));
+            source.roll_back(&target).unwrap();
+        }
+
+        assert_eq!(source.rollback_history().unwrap().len(), 2);
+
+        // replaying the entries of another WAL, the way copy-wal does, doesn't
+        // count as processing those rollbacks again
+        let mut target = WalStore::memory(None).unwrap();
+
+        let entries = source
+            .crawl_from(None)
+            .unwrap()
+            .map(|(_, x)| x)
+            .collect_vec();
+
+        for chunk in entries.chunks(3) {
+            target.append_entries(chunk.iter().cloned()).unwrap();
+        }
+
+        assert!(target.rollback_history().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rollback_history_eviction() {
+        let mut db = testing::db_with_dummy_blocks(10);
+        db.set_max_rollbacks(Some(2));
+
+        for slot in [8, 6, 4] {
+            let target = ChainPoint::Specific(slot, testing::slot_to_hash(slot));
+            db.roll_back(&target).unwrap();
+        }
+
+        let history = db.rollback_history().unwrap();
+        assert_eq!(history.len(), 2);
+
+        // the oldest record (from 9 to 8) was evicted
+        let targets: Vec<_> = history.iter().map(|x| point_slot(&x.to)).collect();
+        assert_eq!(targets, vec![6, 4]);
+    }
+
+    #[test]
+    fn test_truncate_after_unknown_point() {
+        let mut db = testing::db_with_dummy_blocks(3);
+
+        let target = ChainPoint::Specific(20, testing::slot_to_hash(20));
+        let result = db.truncate_after(&target);
+
+        assert!(matches!(result, Err(WalError::PointNotFound(_))));
+    }
+}
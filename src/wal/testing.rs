@@ -20,6 +20,17 @@ pub fn dummy_block_from_slot(slot: u64) -> RawBlock {
     }
 }
 
+/// A dummy block whose slot and hash match the ones of its body
+pub fn valid_dummy_block() -> RawBlock {
+    let mut block = dummy_block_from_slot(0);
+
+    let decoded = pallas::ledger::traverse::MultiEraBlock::decode(&block.body).unwrap();
+    block.slot = decoded.slot();
+    block.hash = decoded.hash();
+
+    block
+}
+
 pub fn empty_db() -> redb::WalStore {
     let mut wal = super::redb::WalStore::memory(None).unwrap();
 
@@ -1,4 +1,3 @@
-use super::reader::ReadUtils;
 use super::*;
 
 pub trait WalWriter: WalReader {
@@ -8,30 +7,48 @@ pub trait WalWriter: WalReader {
         self.append_entries(blocks.map(LogValue::Apply))
     }
 
+    /// Appends the entries of a rollback, which are the undone blocks (newest
+    /// first) followed by a mark of the point the chain was rolled back to
+    ///
+    /// Stores that keep a log of rollbacks record it here, as part of the same
+    /// write as the entries.
+    fn append_rollback(&mut self, undos: Vec<RawBlock>, mark: ChainPoint) -> Result<(), WalError> {
+        let undos = undos.into_iter().map(LogValue::Undo);
+        let mark = std::iter::once(LogValue::Mark(mark));
+
+        self.append_entries(undos.chain(mark))
+    }
+
     fn roll_back(&mut self, until: &ChainPoint) -> Result<(), WalError> {
         let seq = self.assert_point(until)?;
 
-        // find all of the "apply" event in the wall and gather the contained block
-        // data.
-        let applies: Vec<_> = self
-            .crawl_from(Some(seq))?
-            .rev()
-            .filter_apply()
-            .into_blocks()
-            .flatten()
-            .collect();
+        // replay the entries after the target to find the blocks that are still
+        // part of the chain. Blocks undone by an earlier rollback are already out
+        // of it and must not be undone twice.
+        let mut live: Vec<RawBlock> = vec![];
 
-        // take all of the applies, except the last one, and turn them into undo.
-        let undos: Vec<_> = applies
+        for (_, log) in self.crawl_from(Some(seq))? {
+            match log {
+                LogValue::Apply(block) => live.push(block),
+                LogValue::Undo(block) => {
+                    if let Some(idx) = live.iter().rposition(|x| x.hash == block.hash) {
+                        live.truncate(idx);
+                    }
+                }
+                LogValue::Mark(_) => (),
+            }
+        }
+
+        // take all of the live applies, except the target itself, and turn them into
+        // undos, newest first.
+        let undos: Vec<_> = live
             .into_iter()
+            .rev()
             .filter(|x| !ChainPoint::from(x).eq(until))
-            .map(LogValue::Undo)
             .collect();
 
-        // the last one (which is the point the chain is at) is turned into a mark.
-        let mark = std::iter::once(LogValue::Mark(until.clone()));
-
-        self.append_entries(undos.into_iter().chain(mark))?;
+        // the point the chain is at is turned into a mark.
+        self.append_rollback(undos, until.clone())?;
 
         Ok(())
     }